@@ -2,15 +2,15 @@ use std::{
 	collections::hash_map::DefaultHasher,
 	fs,
 	hash::{Hash, Hasher},
+	hint::black_box,
 };
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use glob::glob;
 
 pub fn bench_read_dir(folder: &str) -> usize {
 	std::fs::read_dir(folder)
 		.unwrap()
-		.into_iter()
 		.filter_map(Result::ok)
 		.filter(|f| f.path().extension().unwrap_or_default().eq("acf"))
 		.count()
@@ -23,7 +23,6 @@ pub fn bench_glob(folder: &str) -> usize {
 pub fn bench_hashing(folder: &str) -> u64 {
 	let file_metadata: Vec<_> = fs::read_dir(folder)
 		.unwrap()
-		.into_iter()
 		.filter_map(Result::ok)
 		.filter(|f| f.path().extension().unwrap_or_default().eq("a"))
 		.filter_map(|f| fs::metadata(f.path()).ok())
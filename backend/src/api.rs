@@ -1,32 +1,128 @@
 use crate::{
+	cache::{revision_etag, tagged, IfMatch, TagKind},
 	cfg::CONFIG,
-	ds::Store,
-	dto::{CardEvent, Game, MicroSDCard},
+	ds::{IntegrityIssue, NodeId, Store},
+	dto::{
+		normalize_notes, normalize_tag, CardEvent, CardHistory, CardStats, ChangeKind,
+		CollectionStats, DuplicateGame, Game, GameSource, Group, InternalInstall, MergePlan,
+		MicroSDCard, RemovedGame, TagUsage,
+	},
 	env::PACKAGE_VERSION,
-	err::Error,
-	event::Event,
-	sdcard::{get_card_cid, is_card_inserted},
+	err::{plain_text_errors, Error, ErrorKind},
+	event::{Event, EventBuilder, EventTrait, Published, Replay},
+	export::{Export, ImportReport, ImportStrategy},
+	journal::{JournalEntry, Origin},
+	query::{ListQuery, Page},
+	sdcard::{get_card_cid, get_internal_acf_files, is_card_inserted, read_acf_files},
+	search::SearchHit,
+	ws::{__path_websocket, websocket},
 };
 use actix_web::{
 	delete, get,
 	http::StatusCode,
-	post,
+	middleware::{from_fn, DefaultHeaders},
+	patch, post,
 	web::{self, Bytes},
-	Either, HttpResponse, HttpResponseBuilder, Responder, Result,
+	Either, HttpRequest, HttpResponse, HttpResponseBuilder, Responder, Result,
 };
-use futures::StreamExt;
-use serde::Deserialize;
-use std::{ops::Deref, sync::Arc};
-use tokio::sync::broadcast::Sender;
-use tokio_stream::wrappers::BroadcastStream;
-use tracing::{instrument, trace};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, ops::Deref, sync::Arc};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tracing::{debug, instrument, trace};
+use utoipa::{openapi::Server, IntoParams, OpenApi, ToSchema};
+
+/// Versions of the API served under `/<version>`, oldest first
+pub const API_VERSIONS: [&str; 2] = ["v1", "v2"];
+
+/// Version served by the unprefixed routes. These are deprecated and only kept for frontends bundled in other plugins
+pub const UNPREFIXED_VERSION: &str = "v1";
+
+/// Version of the API a request was routed through, for the handlers whose behaviour differs between versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+	V1,
+	V2,
+}
 
 pub(crate) fn config(cfg: &mut web::ServiceConfig) {
+	cfg //
+		.service(version)
+		.service(list_versions)
+		.service(
+			web::scope("/v1")
+				.wrap(from_fn(plain_text_errors))
+				.configure(v1),
+		)
+		.service(web::scope("/v2").configure(v2))
+		.service(
+			web::scope("")
+				.wrap(from_fn(plain_text_errors))
+				.wrap(
+					DefaultHeaders::new()
+						.add(("Deprecation", "true"))
+						.add(("Link", "</versions>; rel=\"deprecation\"")),
+				)
+				.configure(v1),
+		);
+}
+
+/// The routes as they behaved before the API was versioned: errors are plain text, `/listen` only sends the bare event names
+/// and adding a game which exists or claims to come from Steam is silently adjusted. Routes added since are served as in v2
+fn v1(cfg: &mut web::ServiceConfig) {
+	cfg.app_data(web::Data::new(ApiVersion::V1))
+		.configure(routes);
+}
+
+/// Errors are JSON, events carry ids & payloads and games are added exactly as sent or rejected
+fn v2(cfg: &mut web::ServiceConfig) {
+	cfg.app_data(web::Data::new(ApiVersion::V2))
+		.configure(routes);
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
 	cfg //
 		.service(health)
 		.service(version)
+		.service(list_versions)
+		.service(get_openapi)
 		.service(listen)
+		.service(websocket)
+		.service(list_changes)
 		.service(save)
+		.service(export)
+		.service(import)
+		.service(get_integrity)
+		.service(repair)
+		.service(list_journal)
+		.service(undo_last)
+		.service(undo_entry)
+		.service(list_card_history)
+		.service(list_duplicates)
+		.service(get_collection_stats)
+		.service(get_card_stats)
+		.service(list_tags)
+		.service(rename_tag)
+		.service(delete_tag)
+		.service(add_card_tag)
+		.service(remove_card_tag)
+		.service(set_card_notes)
+		.service(add_game_tag)
+		.service(remove_game_tag)
+		.service(set_game_notes)
+		.service(list_groups)
+		.service(get_group)
+		.service(create_group)
+		.service(delete_group)
+		.service(add_card_to_group)
+		.service(remove_card_from_group)
+		.service(reorder_group)
+		.service(list_groups_for_card)
+		.service(archive_card)
+		.service(restore_card)
+		.service(merge_card)
+		.service(get_removed_game)
+		.service(get_card_history)
 		.service(get_setting_by_name)
 		.service(set_setting_by_name)
 		.service(get_current_card)
@@ -34,11 +130,14 @@ pub(crate) fn config(cfg: &mut web::ServiceConfig) {
 		.service(get_current_card_and_games)
 		.service(get_games_on_current_card)
 		.service(create_card)
+		.service(patch_card)
+		.service(patch_cards)
 		.service(delete_card)
 		.service(update_cards)
 		.service(list_cards)
 		.service(get_card)
 		.service(create_game)
+		.service(patch_game)
 		.service(create_games)
 		.service(delete_game)
 		.service(list_games)
@@ -46,19 +145,176 @@ pub(crate) fn config(cfg: &mut web::ServiceConfig) {
 		.service(list_games_for_card)
 		.service(list_cards_for_game)
 		.service(list_cards_with_games)
+		.service(search)
 		.service(create_link)
 		.service(create_links)
 		.service(delete_link)
 		.service(delete_links);
 }
 
+/// OpenAPI document of the routes registered in [config]
+#[derive(OpenApi)]
+#[openapi(
+	paths(
+		health,
+		version,
+		list_versions,
+		get_openapi,
+		listen,
+		websocket,
+		list_changes,
+		save,
+		export,
+		import,
+		get_integrity,
+		repair,
+		list_journal,
+		undo_last,
+		undo_entry,
+		list_card_history,
+		list_duplicates,
+		get_collection_stats,
+		get_card_stats,
+		list_tags,
+		rename_tag,
+		delete_tag,
+		add_card_tag,
+		remove_card_tag,
+		set_card_notes,
+		add_game_tag,
+		remove_game_tag,
+		set_game_notes,
+		list_groups,
+		get_group,
+		create_group,
+		delete_group,
+		add_card_to_group,
+		remove_card_from_group,
+		reorder_group,
+		list_groups_for_card,
+		archive_card,
+		restore_card,
+		merge_card,
+		get_removed_game,
+		get_card_history,
+		get_setting_by_name,
+		set_setting_by_name,
+		get_current_card,
+		get_current_card_id,
+		get_current_card_and_games,
+		get_games_on_current_card,
+		create_card,
+		patch_card,
+		patch_cards,
+		delete_card,
+		update_cards,
+		list_cards,
+		get_card,
+		create_game,
+		patch_game,
+		create_games,
+		delete_game,
+		list_games,
+		get_game,
+		list_games_for_card,
+		list_cards_for_game,
+		list_cards_with_games,
+		search,
+		create_link,
+		create_links,
+		delete_link,
+		delete_links
+	),
+	components(schemas(
+		MicroSDCard,
+		Game,
+		GameSource,
+		Group,
+		LinkBody,
+		ManyLinkBody,
+		ApiVersions,
+		Error,
+		ErrorKind,
+		CardEvent,
+		ChangeKind
+	)),
+	info(title = "MicroSDeck")
+)]
+pub(crate) struct ApiDoc;
+
+#[utoipa::path(
+	tag = "meta",
+	responses(
+		(status = 200, description = "This document", body = Object)
+	)
+)]
+#[get("/openapi.json")]
+#[instrument(skip(req))]
+pub(crate) async fn get_openapi(req: HttpRequest) -> impl Responder {
+	trace!("HTTP GET /openapi.json");
+
+	let mut doc = ApiDoc::openapi();
+	// The crate version is not kept in sync, the version file is what gets released
+	doc.info.version = PACKAGE_VERSION.trim().to_string();
+	// Paths are relative to the version the document was requested from
+	let prefix = req.path().trim_end_matches("/openapi.json");
+	doc.servers = Some(vec![Server::new(match prefix {
+		"" => "/",
+		prefix => prefix,
+	})]);
+
+	web::Json(doc)
+}
+
+#[utoipa::path(
+	tag = "meta",
+	responses(
+		(status = 200, description = "Version of the backend", content_type = "text/plain", body = String)
+	)
+)]
 #[get("/version")]
 #[instrument]
 pub(crate) async fn version() -> impl Responder {
 	HttpResponse::Ok().body(PACKAGE_VERSION)
 }
 
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ApiVersions {
+	/// Version of the backend, the same as `/version`
+	backend: String,
+	/// Versions served under `/<version>`, oldest first
+	supported: Vec<String>,
+	latest: String,
+	/// Version served by the deprecated unprefixed routes
+	unprefixed: String,
+}
+
+#[utoipa::path(
+	tag = "meta",
+	responses(
+		(status = 200, description = "Versions of the API the backend serves", body = ApiVersions)
+	)
+)]
+#[get("/versions")]
+#[instrument]
+pub(crate) async fn list_versions() -> impl Responder {
+	trace!("HTTP GET /versions");
+
+	web::Json(ApiVersions {
+		backend: PACKAGE_VERSION.trim().to_string(),
+		supported: API_VERSIONS.map(String::from).to_vec(),
+		latest: API_VERSIONS[API_VERSIONS.len() - 1].to_string(),
+		unprefixed: UNPREFIXED_VERSION.to_string(),
+	})
+}
+
 #[allow(clippy::async_yields_async)]
+#[utoipa::path(
+	tag = "meta",
+	responses(
+		(status = 200, description = "The backend is running")
+	)
+)]
 #[get("/health")]
 #[instrument]
 pub(crate) async fn health() -> impl Responder {
@@ -67,20 +323,135 @@ pub(crate) async fn health() -> impl Responder {
 	HttpResponse::Ok()
 }
 
+#[utoipa::path(
+	tag = "meta",
+	params(
+		("Last-Event-ID" = Option<u64>, Header, description = "Id of the last event received. Events published since are replayed, or a `reset` event is sent if they are no longer retained")
+	),
+	responses(
+		(status = 200, description = "Stream of `CardEvent`s, identified by monotonic ids", content_type = "text/event-stream", body = String)
+	)
+)]
 #[get("/listen")]
-#[instrument]
-pub(crate) async fn listen(sender: web::Data<Sender<CardEvent>>) -> Result<HttpResponse> {
+#[instrument(skip(req, datastore))]
+pub(crate) async fn listen(
+	req: HttpRequest,
+	api_version: web::Data<ApiVersion>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<HttpResponse> {
 	trace!("HTTP GET /listen");
 
-	let event_stream = BroadcastStream::new(sender.subscribe()).map(|res| match res {
-		Err(_) => Err(Error::from_str("Subscriber Closed")),
-		Ok(value) => Ok(Event::new(value).into()),
+	let api_version = **api_version;
+	let last_id = req
+		.headers()
+		.get("Last-Event-ID")
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.trim().parse::<u64>().ok())
+		.filter(|_| api_version != ApiVersion::V1);
+
+	// v1 clients only know the bare event names & reload everything on each of them, which also covers a reset
+	let encode = move |event: Published<CardEvent>| -> Bytes {
+		match api_version {
+			ApiVersion::V1 => {
+				Event::new(EventBuilder::new().with_event(event.get_event().unwrap_or("update")))
+					.into()
+			}
+			ApiVersion::V2 => Event::new(event).into(),
+		}
+	};
+	let reset = move || -> Bytes {
+		match api_version {
+			ApiVersion::V1 => Event::new(EventBuilder::new().with_event("update")).into(),
+			ApiVersion::V2 => Event::new(EventBuilder::new().with_event("reset")).into(),
+		}
+	};
+
+	let (replay, receiver) = datastore.subscribe(last_id);
+
+	let replay: Vec<Bytes> = match replay {
+		Replay::Events(events) => events.into_iter().map(encode).collect(),
+		Replay::Reset => vec![reset()],
+	};
+
+	// A subscriber which falls behind has lost events, so it is told to reload just like after a reset
+	let live = BroadcastStream::new(receiver).map(move |res| match res {
+		Ok(event) => encode(event),
+		Err(BroadcastStreamRecvError::Lagged(_)) => reset(),
 	});
+
+	let event_stream = stream::iter(replay).chain(live).map(Ok::<Bytes, Error>);
 	Ok(HttpResponse::Ok()
 		.content_type("text/event-stream")
 		.streaming(event_stream))
 }
 
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct ChangesQuery {
+	/// Revision the client is up to date with, as returned in `ETag`s or by a previous call
+	since: u64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Change {
+	/// Revision of the store after the change
+	revision: u64,
+	event: CardEvent,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Changes {
+	/// Current revision of the store. It can be ahead of the last change, since not every change is reported
+	revision: u64,
+	/// The changes are no longer retained, so everything has to be fetched again
+	reset: bool,
+	/// Changes made after the requested revision, oldest first
+	changes: Vec<Change>,
+}
+
+#[utoipa::path(
+	tag = "meta",
+	params(ChangesQuery),
+	responses(
+		(status = 200, description = "Changes made since the revision, for clients which cannot keep `/listen` open", body = Changes),
+		Error
+	)
+)]
+#[get("/changes")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_changes(
+	query: web::Query<ChangesQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> impl Responder {
+	trace!("HTTP GET /changes");
+
+	let (revision, replay) = datastore.changes_since(query.since);
+	web::Json(match replay {
+		Replay::Events(events) => Changes {
+			revision,
+			reset: false,
+			changes: events
+				.into_iter()
+				.map(|event| Change {
+					revision: event.id,
+					event: event.event,
+				})
+				.collect(),
+		},
+		Replay::Reset => Changes {
+			revision,
+			reset: true,
+			changes: vec![],
+		},
+	})
+}
+
+#[utoipa::path(
+	tag = "settings",
+	responses(
+		(status = 200, description = "Value of the setting", content_type = "text/plain", body = String),
+		Error
+	)
+)]
 #[get("/setting/{name}")]
 #[instrument]
 pub(crate) async fn get_setting_by_name(name: web::Path<String>) -> Result<impl Responder> {
@@ -90,6 +461,14 @@ pub(crate) async fn get_setting_by_name(name: web::Path<String>) -> Result<impl
 	Ok(result)
 }
 
+#[utoipa::path(
+	tag = "settings",
+	request_body(content = String, content_type = "text/plain"),
+	responses(
+		(status = 200, description = "The setting was changed"),
+		Error
+	)
+)]
 #[post("/setting/{name}")]
 #[instrument]
 pub(crate) async fn set_setting_by_name(
@@ -99,20 +478,73 @@ pub(crate) async fn set_setting_by_name(
 	trace!("HTTP POST /setting/{name}");
 
 	let value = String::from_utf8(body.to_vec())
-		.map_err(|_| Error::from_str("Unable to decode body as utf8"))?;
+		.map_err(|_| Error::invalid_input("Unable to decode body as utf8"))?;
 	CONFIG.write().await.set_property(&name, &value)?;
 	Ok(HttpResponse::Ok())
 }
 
-#[get("/list")]
+/// Responds with the items of a page, passing the cursor of the next page along in the `X-Next-Cursor` header
+fn paged<T: Serialize>(page: Page<T>) -> HttpResponse {
+	let mut response = HttpResponse::Ok();
+
+	if let Some(next) = page.next {
+		response.insert_header(("X-Next-Cursor", next));
+	}
+
+	response.json(page.items)
+}
+
+#[utoipa::path(
+	tag = "cards",
+	params(ListQuery),
+	responses(
+		(status = 200, description = "Cards together with their games", body = Vec<(MicroSDCard, Vec<Game>)>, headers(("X-Next-Cursor" = String, description = "Cursor of the following page, if there is one"))),
+		Error
+	)
+)]
+#[get("/list", wrap = "from_fn(revision_etag)")]
 #[instrument(skip(datastore))]
-pub(crate) async fn list_cards_with_games(datastore: web::Data<Arc<Store>>) -> impl Responder {
+pub(crate) async fn list_cards_with_games(
+	query: web::Query<ListQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
 	trace!("HTTP GET /list");
 
-	web::Json(datastore.list_cards_with_games())
+	Ok(paged(datastore.query_cards_with_games(&query)?))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct SearchQuery {
+	q: String,
+	limit: Option<usize>,
 }
 
-#[get("/list/games/{card_id}")]
+#[utoipa::path(
+	tag = "meta",
+	params(SearchQuery),
+	responses(
+		(status = 200, description = "Matches ordered by score", body = Vec<SearchHit>)
+	)
+)]
+#[get("/search", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn search(
+	query: web::Query<SearchQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> impl Responder {
+	trace!("HTTP GET /search");
+
+	web::Json(datastore.search(&query.q, query.limit.unwrap_or(20)))
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "Games on the card", body = Vec<Game>),
+		Error
+	)
+)]
+#[get("/list/games/{card_id}", wrap = "from_fn(revision_etag)")]
 #[instrument(skip(datastore))]
 pub(crate) async fn list_games_for_card(
 	card_id: web::Path<String>,
@@ -126,7 +558,14 @@ pub(crate) async fn list_games_for_card(
 	}
 }
 
-#[get("/list/cards/{game_id}")]
+#[utoipa::path(
+	tag = "games",
+	responses(
+		(status = 200, description = "Cards the game is on", body = Vec<MicroSDCard>),
+		Error
+	)
+)]
+#[get("/list/cards/{game_id}", wrap = "from_fn(revision_etag)")]
 #[instrument(skip(datastore))]
 pub(crate) async fn list_cards_for_game(
 	game_id: web::Path<String>,
@@ -140,6 +579,14 @@ pub(crate) async fn list_cards_for_game(
 	}
 }
 
+#[utoipa::path(
+	tag = "current",
+	responses(
+		(status = 200, description = "The inserted card together with its games", body = (MicroSDCard, Vec<Game>)),
+		(status = 204, description = "No card is inserted or its id could not be resolved"),
+		Error
+	)
+)]
 #[get("/current")]
 #[instrument(skip(datastore))]
 pub(crate) async fn get_current_card_and_games(
@@ -165,32 +612,53 @@ pub(crate) async fn get_current_card_and_games(
 	}
 }
 
+#[utoipa::path(
+	tag = "current",
+	responses(
+		(status = 200, description = "The inserted card", body = MicroSDCard),
+		Error
+	)
+)]
 #[get("/current/card")]
 #[instrument(skip(datastore))]
 pub(crate) async fn get_current_card(datastore: web::Data<Arc<Store>>) -> Result<impl Responder> {
 	trace!("HTTP GET /current/card");
 
 	if !is_card_inserted() {
-		return Err(Error::from_str("No card is inserted").into());
+		return Err(Error::unavailable("No card is inserted").into());
 	}
 
-	let uid = get_card_cid().ok_or(Error::from_str("Unable to evaluate Card Id"))?;
+	let uid = get_card_cid().ok_or(Error::unavailable("Unable to evaluate Card Id"))?;
 
 	Ok(web::Json(datastore.get_card(&uid)?))
 }
 
+#[utoipa::path(
+	tag = "current",
+	responses(
+		(status = 200, description = "Id of the inserted card", content_type = "text/plain", body = String),
+		Error
+	)
+)]
 #[get("/current/id")]
 #[instrument]
 pub(crate) async fn get_current_card_id() -> Result<impl Responder> {
 	trace!("HTTP GET /current/id");
 
 	if !is_card_inserted() {
-		return Err(Error::from_str("No card is inserted").into());
+		return Err(Error::unavailable("No card is inserted").into());
 	}
 
-	Ok(get_card_cid().ok_or(Error::from_str("Unable to evaluate Card Id"))?)
+	Ok(get_card_cid().ok_or(Error::unavailable("Unable to evaluate Card Id"))?)
 }
 
+#[utoipa::path(
+	tag = "current",
+	responses(
+		(status = 200, description = "Games on the inserted card", body = Vec<Game>),
+		Error
+	)
+)]
 #[get("/current/games")]
 #[instrument(skip(datastore))]
 pub(crate) async fn get_games_on_current_card(
@@ -199,10 +667,10 @@ pub(crate) async fn get_games_on_current_card(
 	trace!("HTTP GET /current/games");
 
 	if !is_card_inserted() {
-		return Err(Error::from_str("No card is inserted").into());
+		return Err(Error::unavailable("No card is inserted").into());
 	}
 
-	let uid = get_card_cid().ok_or(Error::from_str("Unable to evaluate Card Id"))?;
+	let uid = get_card_cid().ok_or(Error::unavailable("Unable to evaluate Card Id"))?;
 
 	match datastore.get_games_on_card(&uid) {
 		Ok(value) => Ok(web::Json(value)),
@@ -210,117 +678,456 @@ pub(crate) async fn get_games_on_current_card(
 	}
 }
 
+#[utoipa::path(
+	tag = "cards",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The card was added or updated"),
+		Error
+	)
+)]
 #[post("/card/{id}")]
 #[instrument(skip(datastore))]
 pub(crate) async fn create_card(
 	id: web::Path<String>,
 	body: web::Json<MicroSDCard>,
+	if_match: IfMatch,
 	datastore: web::Data<Arc<Store>>,
-	sender: web::Data<Sender<CardEvent>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP POST /card/{id}");
 
 	if *id != body.uid {
-		return Err(Error::from_str("uid did not match id provided").into());
+		return Err(Error::invalid_input("uid did not match id provided").into());
 	}
 
-	match datastore.contains_element(&id) {
+	if datastore.contains_element(&NodeId::Game(id.to_string())) {
+		return Err(Error::conflict("Id is already used by a game").into());
+	}
+
+	let expected = if_match.revisions(TagKind::Card)?;
+	match datastore.contains_element(&NodeId::Card(id.to_string())) {
 		// Merge the records allowing us to update all properties
-		true => datastore.update_card(&id, move |existing_card| {
-			existing_card.merge(body.deref())?;
-			Ok(())
-		})?,
+		true => datastore.update_card(
+			Origin::Api,
+			&id,
+			expected.as_deref(),
+			move |existing_card| {
+				existing_card.merge(body.deref())?;
+				Ok(())
+			},
+		)?,
 		// Insert a new card if it doesn't exist
-		false => datastore.add_card(id.into_inner(), body.into_inner()),
+		false => datastore.add_card(Origin::Api, body.into_inner())?,
 	}
 
-	_ = sender.send(CardEvent::Updated);
 	Ok(HttpResponse::Ok())
 }
 
+#[utoipa::path(
+	tag = "cards",
+	params(IfMatch),
+	request_body(content = Object, content_type = "application/merge-patch+json", description = "JSON merge patch of the name, position, hidden, tags or notes of the card"),
+	responses(
+		(status = 200, description = "The card was changed"),
+		Error
+	)
+)]
+#[patch("/card/{id}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn patch_card(
+	id: web::Path<String>,
+	body: web::Json<serde_json::Value>,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP PATCH /card/{id}");
+
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.update_card(Origin::Api, &id, expected.as_deref(), |card| {
+		card.patch(&body)
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "cards",
+	request_body(content = HashMap<String, Object>, content_type = "application/merge-patch+json", description = "JSON merge patches by card id. Either every card changes or none does"),
+	responses(
+		(status = 200, description = "The cards were changed"),
+		Error
+	)
+)]
+#[patch("/cards")]
+#[instrument(skip(datastore))]
+pub(crate) async fn patch_cards(
+	body: web::Json<HashMap<String, serde_json::Value>>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP PATCH /cards");
+
+	let card_ids: Vec<String> = body.keys().cloned().collect();
+	datastore.update_cards(Origin::Api, &card_ids, |card| match body.get(&card.uid) {
+		Some(patch) => card.patch(patch),
+		None => Ok(()),
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "The card was deleted"),
+		Error
+	)
+)]
 #[delete("/card/{id}")]
 #[instrument(skip(datastore))]
 pub(crate) async fn delete_card(
 	id: web::Path<String>,
 	datastore: web::Data<Arc<Store>>,
-	sender: web::Data<Sender<CardEvent>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP DELETE /card/{id}");
-	datastore.remove_element(&id)?;
+	datastore.remove_element(Origin::Api, &NodeId::Card(id.into_inner()))?;
 
-	trace!("Sending Updated event");
-	_ = sender.send(CardEvent::Updated);
 	Ok(HttpResponse::Ok())
 }
 
-#[get("/card/{id}")]
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct ArchiveQuery {
+	reason: Option<String>,
+}
+
+#[utoipa::path(
+	tag = "cards",
+	params(ArchiveQuery),
+	responses(
+		(status = 200, description = "The card was archived"),
+		Error
+	)
+)]
+#[post("/card/{id}/archive")]
+#[instrument(skip(datastore))]
+pub(crate) async fn archive_card(
+	id: web::Path<String>,
+	query: web::Query<ArchiveQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /card/{id}/archive");
+
+	let reason = query
+		.into_inner()
+		.reason
+		.and_then(|reason| normalize_notes(&reason));
+	datastore.archive_card(Origin::Api, &id, reason)?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "The card was restored"),
+		Error
+	)
+)]
+#[post("/card/{id}/restore")]
+#[instrument(skip(datastore))]
+pub(crate) async fn restore_card(
+	id: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /card/{id}/restore");
+
+	datastore.restore_card(Origin::Api, &id)?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct MergeQuery {
+	#[serde(default)]
+	retire: bool,
+	#[serde(default)]
+	dry_run: bool,
+}
+
+#[utoipa::path(
+	tag = "cards",
+	params(MergeQuery),
+	responses(
+		(status = 200, description = "What the merge changes", body = MergePlan),
+		Error
+	)
+)]
+#[post("/card/{id}/merge/{target_id}")]
 #[instrument(skip(datastore))]
+pub(crate) async fn merge_card(
+	path: web::Path<(String, String)>,
+	query: web::Query<MergeQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	let (id, target_id) = path.into_inner();
+	trace!("HTTP POST /card/{id}/merge/{target_id}");
+
+	let plan = datastore.merge_cards(Origin::Api, &id, &target_id, query.retire, query.dry_run)?;
+
+	Ok(web::Json(plan))
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "The card", body = MicroSDCard, headers(("ETag" = String, description = "Revision of the card, to be sent as `If-Match` when changing it"))),
+		(status = 304, description = "The card is still at the revision sent as `If-None-Match`"),
+		Error
+	)
+)]
+#[get("/card/{id}")]
+#[instrument(skip(req, datastore))]
 pub(crate) async fn get_card(
+	req: HttpRequest,
 	id: web::Path<String>,
 	datastore: web::Data<Arc<Store>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP GET /card/{id}");
-	Ok(web::Json(datastore.get_card(&id)?))
+
+	let (card, revision) = datastore.get_card_with_revision(&id)?;
+	Ok(tagged(&req, TagKind::Card, revision, &card))
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "History of the card", body = CardHistory),
+		Error
+	)
+)]
+#[get("/card/{id}/history", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn get_card_history(
+	id: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP GET /card/{id}/history");
+	Ok(web::Json(datastore.get_card_history(&id)?))
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "Statistics of the card", body = CardStats),
+		Error
+	)
+)]
+#[get("/card/{id}/stats", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn get_card_stats(
+	id: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP GET /card/{id}/stats");
+	Ok(web::Json(datastore.get_card_stats(&id)?))
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "Statistics of all cards", body = CollectionStats)
+	)
+)]
+#[get("/stats", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn get_collection_stats(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP GET /stats");
+	web::Json(datastore.get_collection_stats())
+}
+
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "History of every card by card id", body = HashMap<String, CardHistory>)
+	)
+)]
+#[get("/history", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_card_history(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP GET /history");
+	web::Json(datastore.list_card_history())
+}
+
+#[utoipa::path(
+	tag = "games",
+	responses(
+		(status = 200, description = "Games installed in more than one place", body = Vec<DuplicateGame>)
+	)
+)]
+#[get("/duplicates")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_duplicates(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP GET /duplicates");
+
+	// The internal storage is only taken into account when its library can be read
+	let internal: HashMap<String, InternalInstall> = match get_internal_acf_files() {
+		Ok(files) => read_acf_files(files)
+			.into_iter()
+			.map(|app| {
+				let install = InternalInstall {
+					size: app.size_on_disk,
+					build_id: app.buildid,
+				};
+				(app.appid, install)
+			})
+			.collect(),
+		Err(err) => {
+			debug!(%err, "Unable to read internal Steam library");
+			HashMap::new()
+		}
+	};
+
+	web::Json(datastore.find_duplicates(&internal))
 }
 
+#[utoipa::path(
+	tag = "cards",
+	responses(
+		(status = 200, description = "The cards were added or updated"),
+		Error
+	)
+)]
 #[post("/cards")]
 #[instrument(skip(datastore))]
 pub(crate) async fn update_cards(
 	body: web::Json<Vec<MicroSDCard>>,
 	datastore: web::Data<Arc<Store>>,
-	sender: web::Data<Sender<CardEvent>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP POST /cards");
 
-	for card in body.iter() {
-		let card = card.to_owned();
-
-		match datastore.contains_element(&card.uid) {
-			// Merge the records allowing us to update all properties
-			true => datastore.update_card(&card.uid.clone(), move |existing_card| {
-				existing_card.merge(&card)?;
-				Ok(())
-			})?,
-			// Insert a new card if it doesn't exist
-			false => datastore.add_card(card.uid.clone(), card),
-		}
+	if let Some(card) = body
+		.iter()
+		.find(|card| datastore.contains_element(&NodeId::Game(card.uid.clone())))
+	{
+		return Err(
+			Error::conflict(&format!("Id \"{}\" is already used by a game", card.uid)).into(),
+		);
 	}
 
-	trace!("Sending Updated event");
-	_ = sender.send(CardEvent::Updated);
+	datastore.upsert_cards(Origin::Api, &body)?;
+
 	Ok(HttpResponse::Ok())
 }
 
-#[get("/cards")]
+#[utoipa::path(
+	tag = "cards",
+	params(ListQuery),
+	responses(
+		(status = 200, description = "Cards", body = Vec<MicroSDCard>, headers(("X-Next-Cursor" = String, description = "Cursor of the following page, if there is one"))),
+		Error
+	)
+)]
+#[get("/cards", wrap = "from_fn(revision_etag)")]
 #[instrument(skip(datastore))]
-pub(crate) async fn list_cards(datastore: web::Data<Arc<Store>>) -> impl Responder {
+pub(crate) async fn list_cards(
+	query: web::Query<ListQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
 	trace!("HTTP GET /cards");
-	web::Json(datastore.list_cards())
+	Ok(paged(datastore.query_cards(&query)?))
 }
 
+/// Adds games as sent by a client, either all of them or none. For v1 clients games claiming to come from Steam are
+/// added as manual ones instead, and sending a game which exists already changes nothing
+fn add_games(
+	datastore: &Store,
+	api_version: ApiVersion,
+	mut games: Vec<Game>,
+) -> Result<(), Error> {
+	let v1 = api_version == ApiVersion::V1;
+	for game in games.iter_mut() {
+		if v1 && game.source.is_steam() && !cfg!(debug_assertions) {
+			game.source = GameSource::Manual;
+		}
+		check_source(game)?;
+	}
+
+	datastore.add_games(Origin::Api, &games, v1)
+}
+
+/// Games from Steam manifests are added by the watcher, which also removes them again. Debug builds may add them to test without a card
+fn check_source(game: &Game) -> Result<(), Error> {
+	match game.source.is_steam() && !cfg!(debug_assertions) {
+		true => Err(Error::invalid_input(&format!(
+			"Game \"{}\" cannot be added as coming from a Steam manifest",
+			game.uid
+		))),
+		false => Ok(()),
+	}
+}
+
+#[utoipa::path(
+	tag = "games",
+	responses(
+		(status = 200, description = "The game was added"),
+		Error
+	)
+)]
 #[post("/game/{id}")]
 #[instrument(skip(datastore))]
 pub(crate) async fn create_game(
 	id: web::Path<String>,
 	body: web::Json<Game>,
+	api_version: web::Data<ApiVersion>,
 	datastore: web::Data<Arc<Store>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP POST /game/{id}");
 
 	if *id != body.uid {
-		return Err(Error::from_str("uid did not match id provided").into());
+		return Err(Error::invalid_input("uid did not match id provided").into());
 	}
 
-	let mut game = body.to_owned();
-
-	if !cfg!(debug_assertions) {
-		game.is_steam = false;
+	if datastore.contains_element(&NodeId::Card(id.to_string())) {
+		return Err(Error::conflict("Id is already used by a card").into());
 	}
 
-	datastore.add_game(id.into_inner(), game);
+	add_games(&datastore, **api_version, vec![body.into_inner()])?;
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "games",
+	params(IfMatch),
+	request_body(content = Object, content_type = "application/merge-patch+json", description = "JSON merge patch of the name, size, source, tags or notes of the game. Fields the watcher owns for the source of the game cannot be changed"),
+	responses(
+		(status = 200, description = "The game was changed"),
+		Error
+	)
+)]
+#[patch("/game/{id}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn patch_game(
+	id: web::Path<String>,
+	body: web::Json<serde_json::Value>,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP PATCH /game/{id}");
+
+	let expected = if_match.revisions(TagKind::Game)?;
+	datastore.update_game(Origin::Api, &id, expected.as_deref(), |game| {
+		game.patch(&body)
+	})?;
+
 	Ok(HttpResponse::Ok())
 }
 
+#[utoipa::path(
+	tag = "games",
+	responses(
+		(status = 200, description = "The game was deleted"),
+		Error
+	)
+)]
 #[delete("/game/{id}")]
 #[instrument(skip(datastore))]
 pub(crate) async fn delete_game(
@@ -328,132 +1135,629 @@ pub(crate) async fn delete_game(
 	datastore: web::Data<Arc<Store>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP DELETE /game/{id}");
-	datastore.remove_element(&id)?;
+	datastore.remove_element(Origin::Api, &NodeId::Game(id.into_inner()))?;
 
 	Ok(HttpResponse::Ok())
 }
 
+#[utoipa::path(
+	tag = "games",
+	responses(
+		(status = 200, description = "The game", body = Game, headers(("ETag" = String, description = "Revision of the game, to be sent as `If-Match` when changing it"))),
+		(status = 304, description = "The game is still at the revision sent as `If-None-Match`"),
+		Error
+	)
+)]
 #[get("/game/{id}")]
-#[instrument(skip(datastore))]
+#[instrument(skip(req, datastore))]
 pub(crate) async fn get_game(
+	req: HttpRequest,
 	id: web::Path<String>,
 	datastore: web::Data<Arc<Store>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP GET /game/{id}");
-	Ok(web::Json(datastore.get_game(&id)?))
+
+	let (game, revision) = datastore.get_game_with_revision(&id)?;
+	Ok(tagged(&req, TagKind::Game, revision, &game))
 }
 
-#[get("/games")]
+#[utoipa::path(
+	tag = "games",
+	responses(
+		(status = 200, description = "Cards the game was removed from", body = Vec<RemovedGame>),
+		Error
+	)
+)]
+#[get("/game/{id}/removed", wrap = "from_fn(revision_etag)")]
 #[instrument(skip(datastore))]
-pub(crate) async fn list_games(datastore: web::Data<Arc<Store>>) -> impl Responder {
+pub(crate) async fn get_removed_game(
+	id: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP GET /game/{id}/removed");
+	Ok(web::Json(datastore.get_removed_game(&id)?))
+}
+
+#[utoipa::path(
+	tag = "games",
+	params(ListQuery),
+	responses(
+		(status = 200, description = "Games", body = Vec<Game>, headers(("X-Next-Cursor" = String, description = "Cursor of the following page, if there is one"))),
+		Error
+	)
+)]
+#[get("/games", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_games(
+	query: web::Query<ListQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
 	trace!("HTTP GET /games");
-	web::Json(datastore.list_games())
+	Ok(paged(datastore.query_games(&query)?))
 }
 
-#[allow(clippy::async_yields_async)]
+#[utoipa::path(
+	tag = "games",
+	responses(
+		(status = 200, description = "The games were added"),
+		Error
+	)
+)]
 #[post("/games")]
 #[instrument(skip(datastore))]
 pub(crate) async fn create_games(
 	body: web::Json<Vec<Game>>,
+	api_version: web::Data<ApiVersion>,
 	datastore: web::Data<Arc<Store>>,
-) -> impl Responder {
+) -> Result<impl Responder> {
 	trace!("HTTP POST /games");
 
-	for game in body.iter() {
-		let mut game = game.to_owned();
-
-		if !cfg!(debug_assertions) {
-			game.is_steam = false;
-		}
-
-		datastore.add_game(game.uid.clone(), game);
+	if let Some(game) = body
+		.iter()
+		.find(|game| datastore.contains_element(&NodeId::Card(game.uid.clone())))
+	{
+		return Err(
+			Error::conflict(&format!("Id \"{}\" is already used by a card", game.uid)).into(),
+		);
 	}
 
-	HttpResponse::Ok()
+	add_games(&datastore, **api_version, body.into_inner())?;
+
+	Ok(HttpResponse::Ok())
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct LinkBody {
 	card_id: String,
 	game_id: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct ManyLinkBody {
 	card_id: String,
 	game_ids: Vec<String>,
 }
 
+#[utoipa::path(
+	tag = "tags",
+	responses(
+		(status = 200, description = "Usage of every tag", body = BTreeMap<String, TagUsage>)
+	)
+)]
+#[get("/tags", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_tags(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP GET /tags");
+	web::Json(datastore.list_tags())
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct RenameQuery {
+	to: String,
+}
+
+#[utoipa::path(
+	tag = "tags",
+	params(RenameQuery),
+	responses(
+		(status = 200, description = "The tag was renamed"),
+		Error
+	)
+)]
+#[post("/tag/{tag}/rename")]
+#[instrument(skip(datastore))]
+pub(crate) async fn rename_tag(
+	tag: web::Path<String>,
+	query: web::Query<RenameQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /tag/{tag}/rename");
+
+	datastore.rename_tag(Origin::Api, &tag, &normalize_tag(&query.to)?)?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "tags",
+	responses(
+		(status = 200, description = "The tag was deleted"),
+		Error
+	)
+)]
+#[delete("/tag/{tag}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn delete_tag(
+	tag: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP DELETE /tag/{tag}");
+
+	datastore.delete_tag(Origin::Api, &tag)?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "tags",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The tag was added to the card"),
+		Error
+	)
+)]
+#[post("/card/{id}/tag/{tag}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn add_card_tag(
+	path: web::Path<(String, String)>,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	let (id, tag) = path.into_inner();
+	trace!("HTTP POST /card/{id}/tag/{tag}");
+
+	let tag = normalize_tag(&tag)?;
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.update_card(Origin::Api, &id, expected.as_deref(), |card| {
+		card.tags.insert(tag.clone());
+		Ok(())
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "tags",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The tag was removed from the card"),
+		Error
+	)
+)]
+#[delete("/card/{id}/tag/{tag}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn remove_card_tag(
+	path: web::Path<(String, String)>,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	let (id, tag) = path.into_inner();
+	trace!("HTTP DELETE /card/{id}/tag/{tag}");
+
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.update_card(Origin::Api, &id, expected.as_deref(), |card| {
+		card.tags.remove(tag.trim());
+		Ok(())
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "cards",
+	request_body(content = String, content_type = "text/plain"),
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The notes were changed"),
+		Error
+	)
+)]
+#[post("/card/{id}/notes")]
+#[instrument(skip(datastore, body))]
+pub(crate) async fn set_card_notes(
+	id: web::Path<String>,
+	body: Bytes,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /card/{id}/notes");
+
+	let notes = String::from_utf8(body.to_vec())
+		.map_err(|_| Error::invalid_input("Unable to decode body as utf8"))?;
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.update_card(Origin::Api, &id, expected.as_deref(), |card| {
+		card.notes = normalize_notes(&notes);
+		Ok(())
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "tags",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The tag was added to the game"),
+		Error
+	)
+)]
+#[post("/game/{id}/tag/{tag}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn add_game_tag(
+	path: web::Path<(String, String)>,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	let (id, tag) = path.into_inner();
+	trace!("HTTP POST /game/{id}/tag/{tag}");
+
+	let tag = normalize_tag(&tag)?;
+	let expected = if_match.revisions(TagKind::Game)?;
+	datastore.update_game(Origin::Api, &id, expected.as_deref(), |game| {
+		game.tags.insert(tag.clone());
+		Ok(())
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "tags",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The tag was removed from the game"),
+		Error
+	)
+)]
+#[delete("/game/{id}/tag/{tag}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn remove_game_tag(
+	path: web::Path<(String, String)>,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	let (id, tag) = path.into_inner();
+	trace!("HTTP DELETE /game/{id}/tag/{tag}");
+
+	let expected = if_match.revisions(TagKind::Game)?;
+	datastore.update_game(Origin::Api, &id, expected.as_deref(), |game| {
+		game.tags.remove(tag.trim());
+		Ok(())
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "games",
+	request_body(content = String, content_type = "text/plain"),
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The notes were changed"),
+		Error
+	)
+)]
+#[post("/game/{id}/notes")]
+#[instrument(skip(datastore, body))]
+pub(crate) async fn set_game_notes(
+	id: web::Path<String>,
+	body: Bytes,
+	if_match: IfMatch,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /game/{id}/notes");
+
+	let notes = String::from_utf8(body.to_vec())
+		.map_err(|_| Error::invalid_input("Unable to decode body as utf8"))?;
+	let expected = if_match.revisions(TagKind::Game)?;
+	datastore.update_game(Origin::Api, &id, expected.as_deref(), |game| {
+		game.notes = normalize_notes(&notes);
+		Ok(())
+	})?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "groups",
+	responses(
+		(status = 200, description = "Groups together with their cards", body = Vec<(Group, Vec<MicroSDCard>)>)
+	)
+)]
+#[get("/groups", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_groups(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP GET /groups");
+	web::Json(datastore.list_groups())
+}
+
+#[utoipa::path(
+	tag = "groups",
+	responses(
+		(status = 200, description = "The group together with its cards", body = (Group, Vec<MicroSDCard>)),
+		Error
+	)
+)]
+#[get("/group/{id}", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn get_group(
+	id: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP GET /group/{id}");
+	Ok(web::Json(datastore.get_group_and_cards(&id)?))
+}
+
+#[utoipa::path(
+	tag = "groups",
+	responses(
+		(status = 200, description = "The group was added or renamed"),
+		Error
+	)
+)]
+#[post("/group/{id}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn create_group(
+	id: web::Path<String>,
+	body: web::Json<Group>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /group/{id}");
+
+	if *id != body.uid {
+		return Err(Error::invalid_input("uid did not match id provided").into());
+	}
+
+	match datastore.contains_element(&NodeId::Group(id.to_string())) {
+		// Only the name can be changed, membership and order have their own endpoints
+		true => datastore.rename_group(Origin::Api, &id, &body.name)?,
+		false => datastore.add_group(
+			Origin::Api,
+			Group {
+				order: vec![],
+				..body.into_inner()
+			},
+		)?,
+	}
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "groups",
+	responses(
+		(status = 200, description = "The group was deleted"),
+		Error
+	)
+)]
+#[delete("/group/{id}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn delete_group(
+	id: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP DELETE /group/{id}");
+	datastore.remove_element(Origin::Api, &NodeId::Group(id.into_inner()))?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct GroupPositionQuery {
+	position: Option<usize>,
+}
+
+#[utoipa::path(
+	tag = "groups",
+	params(GroupPositionQuery),
+	responses(
+		(status = 200, description = "The card was added to the group"),
+		Error
+	)
+)]
+#[post("/group/{id}/card/{card_id}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn add_card_to_group(
+	path: web::Path<(String, String)>,
+	query: web::Query<GroupPositionQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	let (id, card_id) = path.into_inner();
+	trace!("HTTP POST /group/{id}/card/{card_id}");
+
+	datastore.add_to_group(Origin::Api, &id, &card_id, query.position)?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "groups",
+	responses(
+		(status = 200, description = "The card was removed from the group"),
+		Error
+	)
+)]
+#[delete("/group/{id}/card/{card_id}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn remove_card_from_group(
+	path: web::Path<(String, String)>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	let (id, card_id) = path.into_inner();
+	trace!("HTTP DELETE /group/{id}/card/{card_id}");
+
+	datastore.remove_from_group(Origin::Api, &id, &card_id)?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "groups",
+	responses(
+		(status = 200, description = "The group was reordered"),
+		Error
+	)
+)]
+#[post("/group/{id}/order")]
+#[instrument(skip(datastore))]
+pub(crate) async fn reorder_group(
+	id: web::Path<String>,
+	body: web::Json<Vec<String>>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /group/{id}/order");
+
+	datastore.reorder_group(Origin::Api, &id, body.into_inner())?;
+
+	Ok(HttpResponse::Ok())
+}
+
+#[utoipa::path(
+	tag = "groups",
+	responses(
+		(status = 200, description = "Groups the card belongs to", body = Vec<Group>),
+		Error
+	)
+)]
+#[get("/card/{id}/groups", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_groups_for_card(
+	id: web::Path<String>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP GET /card/{id}/groups");
+	Ok(web::Json(datastore.get_groups_for_card(&id)?))
+}
+
+#[utoipa::path(
+	tag = "links",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The game was linked to the card"),
+		Error
+	)
+)]
 #[post("/link")]
 #[instrument(skip(datastore))]
 pub(crate) async fn create_link(
 	body: web::Json<LinkBody>,
+	if_match: IfMatch,
 	datastore: web::Data<Arc<Store>>,
-	sender: web::Data<Sender<CardEvent>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP POST /link");
 
-	datastore.link(&body.game_id, &body.card_id)?;
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.link_games(
+		Origin::Api,
+		&body.card_id,
+		std::slice::from_ref(&body.game_id),
+		expected.as_deref(),
+	)?;
 
-	trace!("Sending Updated event");
-	_ = sender.send(CardEvent::Updated);
 	Ok(HttpResponse::Ok())
 }
 
+#[utoipa::path(
+	tag = "links",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The games were linked to the card"),
+		Error
+	)
+)]
 #[post("/linkmany")]
 #[instrument(skip(datastore))]
 pub(crate) async fn create_links(
 	body: web::Json<ManyLinkBody>,
+	if_match: IfMatch,
 	datastore: web::Data<Arc<Store>>,
-	sender: web::Data<Sender<CardEvent>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP POST /linkmany");
 
-	let data = body.into_inner();
-	for game_id in data.game_ids.iter() {
-		datastore.link(game_id, &data.card_id)?;
-	}
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.link_games(
+		Origin::Api,
+		&body.card_id,
+		&body.game_ids,
+		expected.as_deref(),
+	)?;
 
-	trace!("Sending Updated event");
-	_ = sender.send(CardEvent::Updated);
 	Ok(HttpResponse::Ok())
 }
 
+#[utoipa::path(
+	tag = "links",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The game was unlinked from the card"),
+		Error
+	)
+)]
 #[post("/unlink")]
 #[instrument(skip(datastore))]
 pub(crate) async fn delete_link(
 	body: web::Json<LinkBody>,
+	if_match: IfMatch,
 	datastore: web::Data<Arc<Store>>,
-	sender: web::Data<Sender<CardEvent>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP POST /unlink");
 
-	datastore.unlink(&body.game_id, &body.card_id)?;
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.unlink_games(
+		Origin::Api,
+		&body.card_id,
+		std::slice::from_ref(&body.game_id),
+		expected.as_deref(),
+	)?;
 
-	trace!("Sending Updated event");
-	_ = sender.send(CardEvent::Updated);
 	Ok(HttpResponse::Ok())
 }
 
+#[utoipa::path(
+	tag = "links",
+	params(IfMatch),
+	responses(
+		(status = 200, description = "The games were unlinked from the card"),
+		Error
+	)
+)]
 #[post("/unlinkmany")]
 #[instrument(skip(datastore))]
 pub(crate) async fn delete_links(
 	body: web::Json<ManyLinkBody>,
+	if_match: IfMatch,
 	datastore: web::Data<Arc<Store>>,
-	sender: web::Data<Sender<CardEvent>>,
 ) -> Result<impl Responder> {
 	trace!("HTTP POST /unlinkmany");
 
-	let data = body.into_inner();
-	for game_id in data.game_ids.iter() {
-		datastore.unlink(game_id, &data.card_id)?;
-	}
+	let expected = if_match.revisions(TagKind::Card)?;
+	datastore.unlink_games(
+		Origin::Api,
+		&body.card_id,
+		&body.game_ids,
+		expected.as_deref(),
+	)?;
 
-	trace!("Sending Updated event");
-	_ = sender.send(CardEvent::Updated);
 	Ok(HttpResponse::Ok())
 }
 
+#[utoipa::path(
+	tag = "store",
+	responses(
+		(status = 200, description = "The store was written to disk"),
+		Error
+	)
+)]
 #[post("/save")]
 #[instrument(skip(datastore))]
 pub(crate) async fn save(datastore: web::Data<Arc<Store>>) -> Result<impl Responder> {
@@ -463,3 +1767,145 @@ pub(crate) async fn save(datastore: web::Data<Arc<Store>>) -> Result<impl Respon
 
 	Ok(HttpResponse::Ok())
 }
+
+#[utoipa::path(
+	tag = "store",
+	responses(
+		(status = 200, description = "Copy of the whole store", body = Export)
+	)
+)]
+#[get("/export")]
+#[instrument(skip(datastore))]
+pub(crate) async fn export(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP GET /export");
+	web::Json(datastore.export())
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct ImportQuery {
+	#[param(inline)]
+	strategy: ImportStrategy,
+	#[serde(default)]
+	dry_run: bool,
+}
+
+#[utoipa::path(
+	tag = "store",
+	params(ImportQuery),
+	responses(
+		(status = 200, description = "What the import changes", body = ImportReport),
+		Error
+	)
+)]
+#[post("/import")]
+#[instrument(skip(datastore, body))]
+pub(crate) async fn import(
+	query: web::Query<ImportQuery>,
+	body: web::Json<Export>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /import");
+
+	let report = datastore.import(Origin::Api, &body, query.strategy, query.dry_run)?;
+
+	Ok(web::Json(report))
+}
+
+#[utoipa::path(
+	tag = "store",
+	responses(
+		(status = 200, description = "Inconsistencies found in the store", body = Vec<IntegrityIssue>)
+	)
+)]
+#[get("/integrity", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn get_integrity(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP GET /integrity");
+
+	web::Json(datastore.check_integrity())
+}
+
+#[utoipa::path(
+	tag = "store",
+	responses(
+		(status = 200, description = "The issues which were fixed", body = Vec<IntegrityIssue>)
+	)
+)]
+#[post("/repair")]
+#[instrument(skip(datastore))]
+pub(crate) async fn repair(datastore: web::Data<Arc<Store>>) -> impl Responder {
+	trace!("HTTP POST /repair");
+
+	let fixes = datastore.repair();
+
+	web::Json(fixes)
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct JournalQuery {
+	limit: Option<usize>,
+}
+
+#[utoipa::path(
+	tag = "journal",
+	params(JournalQuery),
+	responses(
+		(status = 200, description = "The most recent entries, newest first", body = Vec<JournalEntry>)
+	)
+)]
+#[get("/journal", wrap = "from_fn(revision_etag)")]
+#[instrument(skip(datastore))]
+pub(crate) async fn list_journal(
+	query: web::Query<JournalQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> impl Responder {
+	trace!("HTTP GET /journal");
+
+	web::Json(datastore.list_journal(query.limit.unwrap_or(50)))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct UndoQuery {
+	count: Option<usize>,
+}
+
+#[utoipa::path(
+	tag = "journal",
+	params(UndoQuery),
+	responses(
+		(status = 200, description = "The entries recorded by the undo", body = Vec<JournalEntry>),
+		Error
+	)
+)]
+#[post("/undo")]
+#[instrument(skip(datastore))]
+pub(crate) async fn undo_last(
+	query: web::Query<UndoQuery>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /undo");
+
+	let reverted = datastore.undo_last(Origin::Api, query.count.unwrap_or(1))?;
+
+	Ok(web::Json(reverted))
+}
+
+#[utoipa::path(
+	tag = "journal",
+	responses(
+		(status = 200, description = "The entries recorded by undoing the change the entry is part of", body = Vec<JournalEntry>),
+		Error
+	)
+)]
+#[post("/undo/{id}")]
+#[instrument(skip(datastore))]
+pub(crate) async fn undo_entry(
+	id: web::Path<u64>,
+	datastore: web::Data<Arc<Store>>,
+) -> Result<impl Responder> {
+	trace!("HTTP POST /undo/{id}");
+
+	let reverted = datastore.undo(Origin::Api, *id)?;
+
+	Ok(web::Json(reverted))
+}
@@ -0,0 +1,109 @@
+use crate::err::Error;
+use actix_web::{
+	body::MessageBody,
+	dev::{ServiceRequest, ServiceResponse},
+	http::{
+		header::{HeaderMap, AUTHORIZATION},
+		Method,
+	},
+	middleware::Next,
+	web,
+};
+use std::{
+	fmt,
+	fs::{self, OpenOptions},
+	io::Write,
+	os::unix::fs::OpenOptionsExt,
+	path::Path,
+};
+use tracing::info;
+
+/// Number of random bytes in a generated token
+const TOKEN_BYTES: usize = 32;
+
+/// Shared secret clients have to present on every request that changes something
+#[derive(Clone)]
+pub struct Token(String);
+
+impl fmt::Debug for Token {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("Token(<redacted>)")
+	}
+}
+
+impl Token {
+	/// Reads the token from `path`, generating & writing a new one the first time the backend runs
+	pub fn load_or_create(path: &Path) -> Result<Self, Error> {
+		if let Ok(token) = fs::read_to_string(path) {
+			let token = token.trim();
+			if !token.is_empty() {
+				return Ok(Token(token.to_string()));
+			}
+		}
+
+		let mut bytes = [0u8; TOKEN_BYTES];
+		getrandom::fill(&mut bytes)?;
+		let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		// Only the deck user, and through it the Decky frontend, may read the token
+		OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.mode(0o600)
+			.open(path)?
+			.write_all(token.as_bytes())?;
+
+		info!(path = %path.display(), "Generated new API token");
+		Ok(Token(token))
+	}
+
+	pub fn matches(&self, presented: &str) -> bool {
+		// Compares every byte so the time taken does not reveal how much of the token was right
+		self.0.len() == presented.len()
+			&& self
+				.0
+				.bytes()
+				.zip(presented.bytes())
+				.fold(0, |diff, (a, b)| diff | (a ^ b))
+				== 0
+	}
+}
+
+/// The token presented as `Authorization: Bearer <token>`
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+	headers
+		.get(AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.map(str::trim)
+}
+
+fn is_mutating(method: &Method) -> bool {
+	!matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Rejects mutating requests which do not carry the token as `Authorization: Bearer <token>`
+pub async fn require_token(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+	if is_mutating(req.method()) {
+		let token = req
+			.app_data::<web::Data<Token>>()
+			.ok_or_else(|| Error::from_str("No API token configured"))?;
+
+		match bearer_token(req.headers()) {
+			None => return Err(Error::unauthorized("Missing API token").into()),
+			Some(presented) if !token.matches(presented) => {
+				return Err(Error::unauthorized("Invalid API token").into())
+			}
+			Some(_) => {}
+		}
+	}
+
+	next.call(req).await
+}
@@ -0,0 +1,222 @@
+use crate::{
+	ds::{NodeId, Store},
+	err::Error,
+};
+use actix_web::{
+	body::{BoxBody, MessageBody},
+	dev::{Payload, ServiceRequest, ServiceResponse},
+	http::header::{HeaderValue, ETAG, IF_MATCH, IF_NONE_MATCH},
+	middleware::Next,
+	web, FromRequest, HttpRequest, HttpResponse,
+};
+use futures::future::{ready, Ready};
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::{
+	openapi::{
+		path::{Parameter, ParameterBuilder, ParameterIn},
+		schema::Type,
+		ObjectBuilder, Required,
+	},
+	IntoParams,
+};
+
+/// What an `ETag` is the revision of. Each kind is tagged differently, so the tag of one cannot be mistaken for another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+	Store,
+	Card,
+	Game,
+	Group,
+}
+
+impl TagKind {
+	fn prefix(&self) -> &'static str {
+		match self {
+			TagKind::Store => "store",
+			TagKind::Card => "card",
+			TagKind::Game => "game",
+			TagKind::Group => "group",
+		}
+	}
+
+	pub fn etag(&self, revision: u64) -> String {
+		format!("\"{}:{revision}\"", self.prefix())
+	}
+
+	/// The revision an opaque tag like `card:12` stands for, if it is of this kind
+	fn revision(&self, opaque: &str) -> Option<u64> {
+		let (prefix, revision) = opaque.split_once(':')?;
+		(prefix == self.prefix()).then(|| revision.parse().ok())?
+	}
+}
+
+impl From<&NodeId> for TagKind {
+	fn from(id: &NodeId) -> Self {
+		match id {
+			NodeId::Card(_) => TagKind::Card,
+			NodeId::Game(_) => TagKind::Game,
+			NodeId::Group(_) => TagKind::Group,
+		}
+	}
+}
+
+fn matches(if_none_match: &str, etag: &str) -> bool {
+	if_none_match.split(',').map(str::trim).any(|candidate| {
+		candidate == "*" || candidate == etag || candidate.strip_prefix("W/") == Some(etag)
+	})
+}
+
+/// Tags responses with the revision of the store as `ETag`, answering `If-None-Match` requests for the current revision with 304.
+/// Only suited to handlers whose response depends on nothing but the store & the request
+pub async fn revision_etag(
+	req: ServiceRequest,
+	next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+	let revision = req
+		.app_data::<web::Data<Arc<Store>>>()
+		.ok_or_else(|| Error::from_str("No datastore configured"))?
+		.revision();
+	// The revision is read before the handler runs, so a change racing the request only leads to a needless refetch later on
+	let etag = TagKind::Store.etag(revision);
+
+	let not_modified = req
+		.headers()
+		.get(IF_NONE_MATCH)
+		.and_then(|value| value.to_str().ok())
+		.is_some_and(|value| matches(value, &etag));
+
+	if not_modified {
+		let response = HttpResponse::NotModified()
+			.insert_header((ETAG, etag))
+			.finish();
+		return Ok(req.into_response(response));
+	}
+
+	let mut response = next.call(req).await?.map_into_boxed_body();
+	if response.status().is_success() {
+		response
+			.headers_mut()
+			.insert(ETAG, HeaderValue::from_str(&etag)?);
+	}
+
+	Ok(response)
+}
+
+/// An entity tag from `If-Match`, without its quotes
+#[derive(Debug, Clone)]
+struct EntityTag {
+	weak: bool,
+	opaque: String,
+}
+
+/// `If-Match` of a change. Without the header, or with `*`, any revision is fine
+#[derive(Debug, Clone, Default)]
+pub struct IfMatch(Option<Vec<EntityTag>>);
+
+impl IfMatch {
+	/// Parses the header as `*` or a comma separated list of entity tags, which may be weak
+	fn parse(value: &str) -> Option<Self> {
+		if value.trim() == "*" {
+			return Some(IfMatch(None));
+		}
+
+		value
+			.split(',')
+			.map(str::trim)
+			.filter(|tag| !tag.is_empty())
+			.map(|tag| {
+				let (weak, tag) = match tag.strip_prefix("W/") {
+					Some(tag) => (true, tag),
+					None => (false, tag),
+				};
+				let opaque = tag.strip_prefix('"')?.strip_suffix('"')?;
+
+				Some(EntityTag {
+					weak,
+					opaque: opaque.to_string(),
+				})
+			})
+			.collect::<Option<Vec<_>>>()
+			.filter(|tags| !tags.is_empty())
+			.map(|tags| IfMatch(Some(tags)))
+	}
+
+	/// The revisions of an element of the given kind, any of which the change may be based on. Tags of another kind are rejected.
+	/// `If-Match` compares strongly, so weak tags never match
+	pub fn revisions(&self, kind: TagKind) -> Result<Option<Vec<u64>>, Error> {
+		let Some(ref tags) = self.0 else {
+			return Ok(None);
+		};
+
+		tags.iter()
+			.map(|tag| {
+				let revision = kind.revision(&tag.opaque).ok_or_else(|| {
+					Error::invalid_input(&format!(
+						"If-Match has to be an ETag of the {}, like {}",
+						kind.prefix(),
+						kind.etag(1)
+					))
+				})?;
+				Ok((!tag.weak).then_some(revision))
+			})
+			.collect::<Result<Vec<_>, Error>>()
+			.map(|revisions| Some(revisions.into_iter().flatten().collect()))
+	}
+}
+
+impl FromRequest for IfMatch {
+	type Error = Error;
+	type Future = Ready<Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		let Some(value) = req.headers().get(IF_MATCH) else {
+			return ready(Ok(IfMatch::default()));
+		};
+
+		ready(
+			value
+				.to_str()
+				.ok()
+				.and_then(IfMatch::parse)
+				.ok_or_else(|| Error::invalid_input("If-Match has to be `*` or a list of ETags")),
+		)
+	}
+}
+
+impl IntoParams for IfMatch {
+	fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+		vec![ParameterBuilder::new()
+			.name("If-Match")
+			.parameter_in(ParameterIn::Header)
+			.required(Required::False)
+			.description(Some(
+				"Revision of the element, as sent in `ETag`, which the change is based on",
+			))
+			.schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+			.build()]
+	}
+}
+
+/// Responds with the element tagged with its revision, or with 304 if the client already has that revision
+pub fn tagged<T: Serialize>(
+	req: &HttpRequest,
+	kind: TagKind,
+	revision: u64,
+	element: &T,
+) -> HttpResponse {
+	let etag = kind.etag(revision);
+
+	let not_modified = req
+		.headers()
+		.get(IF_NONE_MATCH)
+		.and_then(|value| value.to_str().ok())
+		.is_some_and(|value| matches(value, &etag));
+
+	match not_modified {
+		true => HttpResponse::NotModified()
+			.insert_header((ETAG, etag))
+			.finish(),
+		false => HttpResponse::Ok().insert_header((ETAG, etag)).json(element),
+	}
+}
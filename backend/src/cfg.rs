@@ -49,6 +49,21 @@ pub struct Backend {
 	#[serde(with = "LogLevel")]
 	pub log_level: Level,
 	pub startup: Startup,
+	/// Address the HTTP server listens on. Loopback keeps the API off the network
+	#[serde(default = "default_bind_address")]
+	pub bind_address: String,
+	/// Origins allowed to make cross origin requests. `*` allows any origin
+	#[serde(default = "default_allowed_origins")]
+	pub allowed_origins: Vec<String>,
+}
+
+fn default_bind_address() -> String {
+	"127.0.0.1".into()
+}
+
+fn default_allowed_origins() -> Vec<String> {
+	// Origin of the Steam client UI the Decky frontend runs in
+	vec!["https://steamloopback.host".into()]
 }
 
 impl Default for Backend {
@@ -60,6 +75,8 @@ impl Default for Backend {
 			store_file: "store".into(),
 			log_level: Level::INFO,
 			startup: Default::default(),
+			bind_address: default_bind_address(),
+			allowed_origins: default_allowed_origins(),
 		}
 	}
 }
@@ -131,16 +148,20 @@ impl Config {
 				Ok(self.backend.startup.skip_validate.to_string())
 			}
 			["backend", "startup", "skip_clean"] => Ok(self.backend.startup.skip_clean.to_string()),
+			["backend", "bind_address"] => Ok(self.backend.bind_address.clone()),
+			["backend", "allowed_origins"] => {
+				Ok(serde_json::to_string(&self.backend.allowed_origins).unwrap())
+			}
 			["frontend"] => Ok(serde_json::to_string(&self.frontend).unwrap()),
 			["frontend", "dismissed_docs"] => Ok(self.frontend.dismissed_docs.to_string()),
-			_ => Err(Error::from_str("Invalid property Name")),
+			_ => Err(Error::not_found("Invalid property Name")),
 		}
 	}
 
 	pub fn set_property(&mut self, name: &'_ str, value: &'_ str) -> Result<(), Error> {
 		let parts: Vec<&str> = name.split(":").collect();
 
-		let wrong_value_err = Error::from_str(&format!(
+		let wrong_value_err = Error::invalid_input(&format!(
 			"The value provided \"{value}\" did not match the expected type"
 		));
 
@@ -175,13 +196,20 @@ impl Config {
 			["backend", "startup", "skip_clean"] => {
 				self.backend.startup.skip_clean = value.parse().map_err(|_| wrong_value_err)?;
 			}
+			["backend", "bind_address"] => {
+				self.backend.bind_address = value.into();
+			}
+			["backend", "allowed_origins"] => {
+				self.backend.allowed_origins =
+					serde_json::from_str(value).map_err(|_| wrong_value_err)?;
+			}
 			["frontend"] => {
 				self.frontend = serde_json::from_str(value).map_err(|_| wrong_value_err)?;
 			}
 			["frontend", "dismissed_docs"] => {
 				self.frontend.dismissed_docs = value.parse().map_err(|_| wrong_value_err)?;
 			}
-			_ => return Err(Error::from_str("Invalid property Name")),
+			_ => return Err(Error::not_found("Invalid property Name")),
 		}
 
 		self.write()
@@ -1,29 +1,76 @@
 use crate::{
-	dto::{Game, MicroSDCard},
+	cache::TagKind,
+	dto::{
+		largest_games, Archived, CardEvent, CardHistory, CardInstall, CardStats, ChangeKind,
+		CollectionStats, DuplicateGame, Game, Group, InternalInstall, MergePlan, MicroSDCard,
+		RemovedGame, TagUsage,
+	},
 	env::PACKAGE_VERSION,
 	err::Error,
+	event::{EventLog, Published, Replay},
+	export::{plan_import, Export, ImportReport, ImportStrategy},
+	journal::{Journal, JournalEntry, Operation, Origin},
+	query::{paginate, ListQuery, Page, SortKey, SortValues},
 	sdcard::get_steam_acf_files,
+	search::{SearchHit, SearchIndex},
 };
+use chrono::{DateTime, Utc};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use slotmap::{DefaultKey, SlotMap};
 use std::{
 	borrow::BorrowMut,
-	collections::{hash_map::DefaultHasher, HashMap, HashSet},
+	collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap, HashSet},
 	fs::{self, read_to_string, write},
 	hash::{Hash, Hasher},
 	path::PathBuf,
 	sync::RwLock,
 };
-use tracing::{debug, error, instrument};
+use tokio::sync::broadcast::Receiver;
+use tracing::{debug, error, info, instrument, warn};
+use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) enum StoreElement {
 	Game(Game),
 	Card(MicroSDCard),
+	Group(Group),
 }
 
 impl StoreElement {
+	pub fn uid(&self) -> &str {
+		match self {
+			Self::Game(game) => &game.uid,
+			Self::Card(card) => &card.uid,
+			Self::Group(group) => &group.uid,
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Game(game) => &game.name,
+			Self::Card(card) => &card.name,
+			Self::Group(group) => &group.name,
+		}
+	}
+
+	/// Cards can be linked to games and groups. Every other combination is invalid
+	pub fn can_link(&self, other: &StoreElement) -> bool {
+		matches!(
+			(self, other),
+			(Self::Card(_), Self::Game(_) | Self::Group(_))
+				| (Self::Game(_) | Self::Group(_), Self::Card(_))
+		)
+	}
+
+	pub fn id(&self) -> NodeId {
+		match self {
+			Self::Game(game) => NodeId::Game(game.uid.clone()),
+			Self::Card(card) => NodeId::Card(card.uid.clone()),
+			Self::Group(group) => NodeId::Group(group.uid.clone()),
+		}
+	}
+
 	pub fn as_game(&self) -> Option<Game> {
 		match self {
 			Self::Game(game) => Some(game.clone()),
@@ -37,12 +84,22 @@ impl StoreElement {
 			_ => None,
 		}
 	}
+
+	pub fn as_group(&self) -> Option<Group> {
+		match self {
+			Self::Group(group) => Some(group.clone()),
+			_ => None,
+		}
+	}
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct Node {
 	pub(crate) element: StoreElement,
 	pub(crate) links: HashSet<DefaultKey>,
+	/// Revision of the store the element or its links last changed at
+	#[serde(skip)]
+	pub(crate) revision: u64,
 }
 
 impl Node {
@@ -50,12 +107,37 @@ impl Node {
 		Node {
 			element: StoreElement::Card(card),
 			links: HashSet::new(),
+			revision: 0,
 		}
 	}
 	pub fn from_game(game: Game) -> Self {
 		Node {
 			element: StoreElement::Game(game),
 			links: HashSet::new(),
+			revision: 0,
+		}
+	}
+	pub fn from_group(group: Group) -> Self {
+		Node {
+			element: StoreElement::Group(group),
+			links: HashSet::new(),
+			revision: 0,
+		}
+	}
+}
+
+/// Identifies an element within the store. Cards, games and groups live in separate namespaces
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, ToSchema)]
+pub enum NodeId {
+	Card(String),
+	Game(String),
+	Group(String),
+}
+
+impl NodeId {
+	pub fn as_str(&self) -> &str {
+		match self {
+			Self::Card(id) | Self::Game(id) | Self::Group(id) => id,
 		}
 	}
 }
@@ -64,72 +146,595 @@ fn default_version() -> Version {
 	Version::parse(PACKAGE_VERSION).unwrap()
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A single inconsistency found within the store
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub enum IntegrityIssue {
+	/// An id points at a node that no longer exists
+	DeadId { id: NodeId },
+	/// An id points at a node whose element has a different uid
+	MismatchedId { id: NodeId, uid: String },
+	/// An id points at an element of the wrong type
+	WrongElementType { id: NodeId },
+	/// A node links to another node that no longer exists
+	DanglingLink { uid: String },
+	/// A link connects two elements which cannot be linked, anything but a card with a game or group
+	InvalidLink { from: String, to: String },
+	/// A link is only present on one of the two nodes it connects
+	AsymmetricLink { from: String, to: String },
+	/// Multiple elements of the same type share a uid
+	DuplicateUid { uid: String, count: usize },
+	/// A node is not reachable through any id
+	OrphanedNode { uid: String },
+}
+
+/// In memory graph of the store. Only deserialized directly to read stores written before `StoreFile` existed
+#[derive(Deserialize, Debug, Clone)]
 pub struct StoreData {
 	#[serde(default = "default_version")]
 	version: Version,
 	nodes: SlotMap<DefaultKey, Node>,
+	#[serde(default)]
+	card_ids: HashMap<String, DefaultKey>,
+	#[serde(default)]
+	game_ids: HashMap<String, DefaultKey>,
+	#[serde(default)]
+	group_ids: HashMap<String, DefaultKey>,
+	/// Shared id namespace used before cards and games were separated. Only read to migrate older stores
+	#[serde(default, skip_serializing)]
 	node_ids: HashMap<String, DefaultKey>,
 	#[serde(default)]
 	hashes: HashMap<String, u64>,
+	#[serde(default)]
+	history: HashMap<String, CardHistory>,
+	/// Bumped by every change that can be seen through the API
+	#[serde(default)]
+	revision: u64,
+	#[serde(skip)]
+	index: SearchIndex,
+}
+
+/// A card together with the uids of everything it is linked to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CardEntry {
+	#[serde(flatten)]
+	card: MicroSDCard,
+	#[serde(default)]
+	games: Vec<String>,
+	#[serde(default)]
+	groups: Vec<String>,
+}
+
+/// On disk layout of the store. Everything is keyed by uid and sorted so the file can be read, diffed and repaired by hand
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StoreFile {
+	#[serde(default = "default_version")]
+	version: Version,
+	#[serde(default)]
+	cards: BTreeMap<String, CardEntry>,
+	#[serde(default)]
+	games: BTreeMap<String, Game>,
+	#[serde(default)]
+	groups: BTreeMap<String, Group>,
+	#[serde(default)]
+	hashes: BTreeMap<String, u64>,
+	#[serde(default)]
+	history: BTreeMap<String, CardHistory>,
+	#[serde(default)]
+	revision: u64,
+}
+
+impl StoreFile {
+	fn from_data(data: &StoreData) -> Self {
+		let uids = |links: &HashSet<DefaultKey>, kind: fn(&StoreElement) -> bool| {
+			let mut uids: Vec<String> = links
+				.iter()
+				.filter_map(|key| data.nodes.get(*key))
+				.filter(|node| kind(&node.element))
+				.map(|node| node.element.uid().to_string())
+				.collect();
+			uids.sort();
+			uids
+		};
+
+		let mut file = StoreFile {
+			version: data.version.clone(),
+			cards: BTreeMap::new(),
+			games: BTreeMap::new(),
+			groups: BTreeMap::new(),
+			hashes: data.hashes.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+			history: data
+				.history
+				.iter()
+				.map(|(k, v)| (k.clone(), v.clone()))
+				.collect(),
+			revision: data.revision,
+		};
+
+		for node in data.nodes.values() {
+			match node.element {
+				StoreElement::Card(ref card) => {
+					let entry = CardEntry {
+						card: card.clone(),
+						games: uids(&node.links, |element| {
+							matches!(element, StoreElement::Game(_))
+						}),
+						groups: uids(&node.links, |element| {
+							matches!(element, StoreElement::Group(_))
+						}),
+					};
+					file.cards.insert(card.uid.clone(), entry);
+				}
+				StoreElement::Game(ref game) => {
+					file.games.insert(game.uid.clone(), game.clone());
+				}
+				StoreElement::Group(ref group) => {
+					file.groups.insert(group.uid.clone(), group.clone());
+				}
+			}
+		}
+
+		file
+	}
+
+	/// Builds the in memory graph. The key of each entry is authoritative, links to missing elements are dropped
+	fn into_data(self) -> StoreData {
+		let mut data = StoreData {
+			version: self.version,
+			nodes: SlotMap::new(),
+			card_ids: HashMap::new(),
+			game_ids: HashMap::new(),
+			group_ids: HashMap::new(),
+			node_ids: HashMap::new(),
+			hashes: self.hashes.into_iter().collect(),
+			history: self.history.into_iter().collect(),
+			revision: self.revision,
+			index: SearchIndex::default(),
+		};
+
+		for (uid, game) in self.games {
+			data.add_game(uid.clone(), Game { uid, ..game });
+		}
+		for (uid, group) in self.groups {
+			data.add_group(uid.clone(), Group { uid, ..group });
+		}
+
+		for (uid, entry) in self.cards {
+			data.add_card(
+				uid.clone(),
+				MicroSDCard {
+					uid: uid.clone(),
+					..entry.card
+				},
+			);
+
+			for game_id in entry.games {
+				if data.link(&game_id, &uid).is_err() {
+					warn!(card_id = uid, game_id, "Dropping link to missing game");
+				}
+			}
+			for group_id in entry.groups {
+				if data.link_group(&group_id, &uid).is_err() {
+					warn!(
+						card_id = uid,
+						group_id, "Dropping membership of missing group"
+					);
+				}
+			}
+		}
+
+		data
+	}
 }
 
 impl StoreData {
+	pub fn new() -> Self {
+		StoreData {
+			version: Version::parse(PACKAGE_VERSION).unwrap(),
+			nodes: SlotMap::new(),
+			card_ids: HashMap::new(),
+			game_ids: HashMap::new(),
+			group_ids: HashMap::new(),
+			node_ids: HashMap::new(),
+			hashes: HashMap::new(),
+			history: HashMap::new(),
+			revision: 0,
+			index: SearchIndex::default(),
+		}
+	}
+
+	/// Moves ids out of the legacy shared namespace into the namespace matching their element
+	pub fn migrate(&mut self) {
+		if self.node_ids.is_empty() {
+			return;
+		}
+
+		info!(
+			count = self.node_ids.len(),
+			"Migrating {} ids into separate card & game namespaces",
+			self.node_ids.len()
+		);
+
+		for (id, key) in std::mem::take(&mut self.node_ids) {
+			match self.nodes.get(key).map(|node| &node.element) {
+				Some(StoreElement::Card(_)) => {
+					self.card_ids.insert(id, key);
+				}
+				Some(StoreElement::Game(_)) => {
+					self.game_ids.insert(id, key);
+				}
+				Some(StoreElement::Group(_)) => {
+					self.group_ids.insert(id, key);
+				}
+				None => warn!(id, "Dropping id of missing node during migration"),
+			}
+		}
+
+		self.version = default_version();
+	}
+
+	/// Revisions of single elements are not stored, so after loading every element counts as changed at the current revision
+	pub fn reset_revisions(&mut self) {
+		let revision = self.revision;
+		for node in self.nodes.values_mut() {
+			node.revision = revision;
+		}
+	}
+
+	/// Rebuilds the search index from scratch. Needed whenever the store is changed outside of `apply`
+	pub fn rebuild_index(&mut self) {
+		self.index.clear();
+
+		for node in self.nodes.values() {
+			if let StoreElement::Group(_) = node.element {
+				continue;
+			}
+			self.index.insert(node.element.id(), node.element.name());
+		}
+	}
+
+	fn touch(&mut self, id: &NodeId) {
+		let revision = self.revision;
+		if let Some(node) = self
+			.ids(id)
+			.get(id.as_str())
+			.copied()
+			.and_then(|key| self.nodes.get_mut(key))
+		{
+			node.revision = revision;
+		}
+	}
+
+	pub fn revision_of(&self, id: &NodeId) -> Result<u64, Error> {
+		self.ids(id)
+			.get(id.as_str())
+			.and_then(|key| self.nodes.get(*key))
+			.map(|node| node.revision)
+			.ok_or_else(|| Error::not_found("Id not present"))
+	}
+
+	/// Fails with the current state of the element if it changed since the revision the request was based on
+	pub fn check_revision(&self, id: &NodeId, expected: Option<&[u64]>) -> Result<(), Error> {
+		let Some(expected) = expected else {
+			return Ok(());
+		};
+
+		let revision = self.revision_of(id)?;
+		if expected.contains(&revision) {
+			return Ok(());
+		}
+
+		let current = match id {
+			NodeId::Card(card_id) => serde_json::to_value(self.get_card(card_id)?)?,
+			NodeId::Game(game_id) => serde_json::to_value(self.get_game(game_id)?)?,
+			NodeId::Group(group_id) => serde_json::to_value(self.get_group_and_cards(group_id)?.0)?,
+		};
+		Err(Error::revision_mismatch(&format!(
+			"{} \"{}\" is at revision {revision}, which If-Match does not match",
+			match id {
+				NodeId::Card(_) => "Card",
+				NodeId::Game(_) => "Game",
+				NodeId::Group(_) => "Group",
+			},
+			id.as_str()
+		))
+		.with_current(revision, TagKind::from(id).etag(revision), current))
+	}
+
+	fn ids(&self, id: &NodeId) -> &HashMap<String, DefaultKey> {
+		match id {
+			NodeId::Card(_) => &self.card_ids,
+			NodeId::Game(_) => &self.game_ids,
+			NodeId::Group(_) => &self.group_ids,
+		}
+	}
+
+	fn ids_mut(&mut self, id: &NodeId) -> &mut HashMap<String, DefaultKey> {
+		match id {
+			NodeId::Card(_) => &mut self.card_ids,
+			NodeId::Game(_) => &mut self.game_ids,
+			NodeId::Group(_) => &mut self.group_ids,
+		}
+	}
+
+	/// Applies an operation after checking that it is consistent with the current state of the store
 	#[instrument(skip(self))]
-	pub fn add_card(&mut self, id: String, card: MicroSDCard) {
-		self.node_ids
+	pub fn apply(&mut self, operation: &Operation) -> Result<(), Error> {
+		match operation {
+			Operation::AddCard {
+				card,
+				games,
+				groups,
+			} => {
+				if self.card_ids.contains_key(&card.uid) {
+					return Err(Error::conflict("Card already exists"));
+				}
+				if let Some(game_id) = games.iter().find(|id| !self.game_ids.contains_key(*id)) {
+					return Err(Error::not_found(&format!(
+						"Game \"{game_id}\" does not exist"
+					)));
+				}
+				if let Some(group_id) = groups.iter().find(|id| !self.group_ids.contains_key(*id)) {
+					return Err(Error::not_found(&format!(
+						"Group \"{group_id}\" does not exist"
+					)));
+				}
+
+				self.add_card(card.uid.clone(), card.clone());
+				for game_id in games {
+					self.link(game_id, &card.uid)?;
+				}
+				for group_id in groups {
+					self.link_group(group_id, &card.uid)?;
+				}
+			}
+			Operation::RemoveCard {
+				card,
+				games,
+				groups,
+			} => {
+				let (current, current_games) = self.get_card_and_games(&card.uid)?;
+				let current_groups = self.get_groups_for_card(&card.uid)?;
+				if current != *card
+					|| !same_ids(games, current_games.iter().map(|g| &g.uid))
+					|| !same_ids(groups, current_groups.iter().map(|g| &g.uid))
+				{
+					return Err(Error::conflict(
+						"Card has changed since the operation was recorded",
+					));
+				}
+
+				self.remove_item(&NodeId::Card(card.uid.clone()))?;
+				self.delete_hash(&card.uid);
+			}
+			Operation::UpdateCard { before, after } => {
+				if before.uid != after.uid {
+					return Err(Error::invalid_input("Card uid cannot be changed"));
+				}
+
+				self.update_card(&before.uid, |card| {
+					if card != before {
+						return Err(Error::conflict(
+							"Card has changed since the operation was recorded",
+						));
+					}
+					*card = after.clone();
+					Ok(())
+				})?;
+			}
+			Operation::AddGame { game, cards } => {
+				if self.game_ids.contains_key(&game.uid) {
+					return Err(Error::conflict("Game already exists"));
+				}
+				if let Some(card_id) = cards.iter().find(|id| !self.card_ids.contains_key(*id)) {
+					return Err(Error::not_found(&format!(
+						"Card \"{card_id}\" does not exist"
+					)));
+				}
+
+				self.add_game(game.uid.clone(), game.clone());
+				for card_id in cards {
+					self.link(&game.uid, card_id)?;
+				}
+			}
+			Operation::RemoveGame { game, cards } => {
+				let current = self.get_game(&game.uid)?;
+				let current_cards = self.get_cards_for_game(&game.uid)?;
+				if current != *game || !same_ids(cards, current_cards.iter().map(|c| &c.uid)) {
+					return Err(Error::conflict(
+						"Game has changed since the operation was recorded",
+					));
+				}
+
+				self.remove_item(&NodeId::Game(game.uid.clone()))?;
+			}
+			Operation::UpdateGame { before, after } => {
+				if before.uid != after.uid {
+					return Err(Error::invalid_input("Game uid cannot be changed"));
+				}
+
+				self.update_game(&before.uid, |game| {
+					if game != before {
+						return Err(Error::conflict(
+							"Game has changed since the operation was recorded",
+						));
+					}
+					*game = after.clone();
+					Ok(())
+				})?;
+			}
+			Operation::AddGroup { group, cards } => {
+				if self.group_ids.contains_key(&group.uid) {
+					return Err(Error::conflict("Group already exists"));
+				}
+				if let Some(card_id) = cards.iter().find(|id| !self.card_ids.contains_key(*id)) {
+					return Err(Error::not_found(&format!(
+						"Card \"{card_id}\" does not exist"
+					)));
+				}
+
+				self.add_group(group.uid.clone(), group.clone());
+				for card_id in cards {
+					self.link_group(&group.uid, card_id)?;
+				}
+			}
+			Operation::RemoveGroup { group, cards } => {
+				let (current, current_cards) = self.get_group_and_cards(&group.uid)?;
+				if current != *group || !same_ids(cards, current_cards.iter().map(|c| &c.uid)) {
+					return Err(Error::conflict(
+						"Group has changed since the operation was recorded",
+					));
+				}
+
+				self.remove_item(&NodeId::Group(group.uid.clone()))?;
+			}
+			Operation::UpdateGroup { before, after } => {
+				if before.uid != after.uid {
+					return Err(Error::invalid_input("Group uid cannot be changed"));
+				}
+
+				let node = self
+					.group_ids
+					.get(&before.uid)
+					.ok_or(Error::not_found("Group Id not present"))?;
+
+				match self.nodes[*node].element {
+					StoreElement::Group(ref mut group) if group == before => *group = after.clone(),
+					StoreElement::Group(_) => {
+						return Err(Error::conflict(
+							"Group has changed since the operation was recorded",
+						))
+					}
+					_ => return Err(Error::not_found("Id does not belong to a group")),
+				}
+			}
+			Operation::AddToGroup { group_id, card_id } => {
+				if self.is_grouped(group_id, card_id)? {
+					return Err(Error::conflict("Card is already in the group"));
+				}
+				self.link_group(group_id, card_id)?;
+			}
+			Operation::RemoveFromGroup { group_id, card_id } => {
+				if !self.is_grouped(group_id, card_id)? {
+					return Err(Error::conflict("Card is not in the group"));
+				}
+				self.unlink_group(group_id, card_id)?;
+			}
+			Operation::Link { game_id, card_id } => {
+				if self.is_linked(game_id, card_id)? {
+					return Err(Error::conflict("Game and Card are already linked"));
+				}
+				self.link(game_id, card_id)?;
+			}
+			Operation::Unlink { game_id, card_id } => {
+				if !self.is_linked(game_id, card_id)? {
+					return Err(Error::conflict("Game and Card are not linked"));
+				}
+				self.unlink(game_id, card_id)?;
+			}
+			Operation::SetHistory {
+				card_id,
+				before,
+				after,
+			} => {
+				if self.history.get(card_id) != before.as_ref() {
+					return Err(Error::conflict(
+						"History has changed since the operation was recorded",
+					));
+				}
+
+				match after {
+					Some(history) => self.history.insert(card_id.clone(), history.clone()),
+					None => self.history.remove(card_id),
+				};
+			}
+		}
+
+		self.index.update(operation);
+		self.revision += 1;
+		for id in affected_elements(operation) {
+			self.touch(&id);
+		}
+
+		Ok(())
+	}
+
+	fn add_card(&mut self, id: String, card: MicroSDCard) {
+		self.card_ids
 			.entry(id)
 			.or_insert_with(|| self.nodes.insert(Node::from_card(card)));
 	}
 
-	#[instrument(skip(self))]
-	pub fn add_game(&mut self, id: String, game: Game) {
-		self.node_ids
+	fn add_game(&mut self, id: String, game: Game) {
+		self.game_ids
 			.entry(id)
 			.or_insert_with(|| self.nodes.insert(Node::from_game(game)));
 	}
 
-	#[instrument(skip(self, func))]
-	pub fn update_card<F>(&mut self, card_id: &str, mut func: F) -> Result<(), Error>
+	fn add_group(&mut self, id: String, group: Group) {
+		self.group_ids
+			.entry(id)
+			.or_insert_with(|| self.nodes.insert(Node::from_group(group)));
+	}
+
+	fn update_card<F>(&mut self, card_id: &str, mut func: F) -> Result<(), Error>
 	where
 		F: FnMut(&mut MicroSDCard) -> Result<(), Error>,
 	{
 		let node = self
-			.node_ids
+			.card_ids
 			.get(card_id)
-			.ok_or(Error::from_str("Card Id not present"))?;
+			.ok_or(Error::not_found("Card Id not present"))?;
 
 		match self.nodes.get_mut(*node).unwrap().element {
 			StoreElement::Card(ref mut card) => {
 				func(card)?;
 			}
-			StoreElement::Game(_) => return Err(Error::from_str("Expected Card, got Game")),
+			StoreElement::Game(_) => return Err(Error::not_found("Expected Card, got Game")),
+			StoreElement::Group(_) => return Err(Error::not_found("Expected Card, got Group")),
 		}
 
 		Ok(())
 	}
 
-	#[instrument(skip(self))]
-	pub fn link(&mut self, a_id: &str, b_id: &str) -> Result<(), Error> {
-		let a_key = self.node_ids.get(a_id);
-		let b_key = self.node_ids.get(b_id);
-		let (a_key, b_key) = a_key
-			.zip(b_key)
-			.ok_or_else(|| Error::from_str("Either Game or Card could not be found"))?;
+	fn update_game<F>(&mut self, game_id: &str, mut func: F) -> Result<(), Error>
+	where
+		F: FnMut(&mut Game) -> Result<(), Error>,
+	{
+		let node = self
+			.game_ids
+			.get(game_id)
+			.ok_or(Error::not_found("Game Id not present"))?;
 
-		self.nodes[*a_key].links.insert(*b_key);
-		self.nodes[*b_key].links.insert(*a_key);
+		match self.nodes.get_mut(*node).unwrap().element {
+			StoreElement::Game(ref mut game) => {
+				func(game)?;
+			}
+			StoreElement::Card(_) => return Err(Error::not_found("Expected Game, got Card")),
+			StoreElement::Group(_) => return Err(Error::not_found("Expected Game, got Group")),
+		}
 
 		Ok(())
 	}
 
-	#[instrument(skip(self))]
-	pub fn unlink(&mut self, a_id: &str, b_id: &str) -> Result<(), Error> {
-		let game_key = self.node_ids.get(a_id);
-		let card_key = self.node_ids.get(b_id);
+	fn link(&mut self, game_id: &str, card_id: &str) -> Result<(), Error> {
+		let game_key = self.game_ids.get(game_id);
+		let card_key = self.card_ids.get(card_id);
+		let (game_key, card_key) = game_key
+			.zip(card_key)
+			.ok_or_else(|| Error::not_found("Either Game or Card could not be found"))?;
+
+		self.nodes[*game_key].links.insert(*card_key);
+		self.nodes[*card_key].links.insert(*game_key);
+
+		Ok(())
+	}
+
+	fn unlink(&mut self, game_id: &str, card_id: &str) -> Result<(), Error> {
+		let game_key = self.game_ids.get(game_id);
+		let card_key = self.card_ids.get(card_id);
 		let (game_key, card_key) = game_key
 			.zip(card_key)
-			.ok_or_else(|| Error::from_str("Either Game or Card could not be found"))?;
+			.ok_or_else(|| Error::not_found("Either Game or Card could not be found"))?;
 
 		self.nodes[*game_key].links.remove(card_key);
 		self.nodes[*card_key].links.remove(game_key);
@@ -137,55 +742,110 @@ impl StoreData {
 		Ok(())
 	}
 
-	#[instrument(skip(self))]
-	pub fn remove_item(&mut self, id: &str) -> Result<(), Error> {
+	fn group_and_card_keys(
+		&self,
+		group_id: &str,
+		card_id: &str,
+	) -> Result<(DefaultKey, DefaultKey), Error> {
+		let group_key = self.group_ids.get(group_id);
+		let card_key = self.card_ids.get(card_id);
+		group_key
+			.copied()
+			.zip(card_key.copied())
+			.ok_or_else(|| Error::not_found("Either Group or Card could not be found"))
+	}
+
+	fn link_group(&mut self, group_id: &str, card_id: &str) -> Result<(), Error> {
+		let (group_key, card_key) = self.group_and_card_keys(group_id, card_id)?;
+
+		self.nodes[group_key].links.insert(card_key);
+		self.nodes[card_key].links.insert(group_key);
+
+		Ok(())
+	}
+
+	fn unlink_group(&mut self, group_id: &str, card_id: &str) -> Result<(), Error> {
+		let (group_key, card_key) = self.group_and_card_keys(group_id, card_id)?;
+
+		self.nodes[group_key].links.remove(&card_key);
+		self.nodes[card_key].links.remove(&group_key);
+
+		Ok(())
+	}
+
+	fn remove_item(&mut self, id: &NodeId) -> Result<(), Error> {
 		let element_key = self
-			.node_ids
-			.remove(id)
-			.ok_or_else(|| Error::from_str("Id not present"))?;
+			.ids_mut(id)
+			.remove(id.as_str())
+			.ok_or_else(|| Error::not_found("Id not present"))?;
 
-		for key in self.nodes.remove(element_key).unwrap().links {
-			self.nodes[key].links.remove(&element_key);
-		}
+		self.remove_node(element_key);
 
 		Ok(())
 	}
 
+	fn remove_node(&mut self, key: DefaultKey) {
+		if let Some(node) = self.nodes.remove(key) {
+			for link in node.links {
+				if let Some(linked) = self.nodes.get_mut(link) {
+					linked.links.remove(&key);
+				}
+			}
+		}
+	}
+
+	#[instrument(skip(self))]
+	pub fn contains_element(&self, id: &NodeId) -> bool {
+		self.ids(id).contains_key(id.as_str())
+	}
+
+	#[instrument(skip(self))]
+	pub fn is_linked(&self, game_id: &str, card_id: &str) -> Result<bool, Error> {
+		let game_key = self.game_ids.get(game_id);
+		let card_key = self.card_ids.get(card_id);
+		let (game_key, card_key) = game_key
+			.zip(card_key)
+			.ok_or_else(|| Error::not_found("Either Game or Card could not be found"))?;
+
+		Ok(self.nodes[*game_key].links.contains(card_key))
+	}
+
 	#[instrument(skip(self))]
-	pub fn contains_element(&self, card_id: &str) -> bool {
-		self.node_ids.contains_key(card_id)
+	pub fn is_grouped(&self, group_id: &str, card_id: &str) -> Result<bool, Error> {
+		let (group_key, card_key) = self.group_and_card_keys(group_id, card_id)?;
+		Ok(self.nodes[group_key].links.contains(&card_key))
 	}
 
 	#[instrument(skip(self))]
 	pub fn get_card(&self, card_id: &str) -> Result<MicroSDCard, Error> {
-		self.node_ids
+		self.card_ids
 			.get(card_id)
-			.map_or(Error::new_res("Card Id not present"), |key| {
-				Ok(self.nodes[*key]
+			.map_or(Err(Error::not_found("Card Id not present")), |key| {
+				self.nodes[*key]
 					.element
 					.as_card()
-					.expect("Expected card but game was returned"))
+					.ok_or_else(|| Error::not_found("Id does not belong to a card"))
 			})
 	}
 
 	#[instrument(skip(self))]
 	pub fn get_game(&self, game_id: &str) -> Result<Game, Error> {
-		self.node_ids
+		self.game_ids
 			.get(game_id)
-			.map_or(Error::new_res("Game Id not present"), |key| {
-				Ok(self.nodes[*key]
+			.map_or(Err(Error::not_found("Game Id not present")), |key| {
+				self.nodes[*key]
 					.element
 					.as_game()
-					.expect("Expected game but card was returned"))
+					.ok_or_else(|| Error::not_found("Id does not belong to a game"))
 			})
 	}
 
 	#[instrument(skip(self))]
 	pub fn get_card_and_games(&self, card_id: &str) -> Result<(MicroSDCard, Vec<Game>), Error> {
 		let card_key = self
-			.node_ids
+			.card_ids
 			.get(card_id)
-			.ok_or_else(|| Error::from_str("Card Id not present"))?;
+			.ok_or_else(|| Error::not_found("Card Id not present"))?;
 
 		let node = &self.nodes[*card_key];
 
@@ -205,9 +865,9 @@ impl StoreData {
 	#[instrument(skip(self))]
 	pub fn get_games_on_card(&self, card_id: &str) -> Result<Vec<Game>, Error> {
 		let card_key = self
-			.node_ids
+			.card_ids
 			.get(card_id)
-			.ok_or_else(|| Error::from_str("Card Id not present"))?;
+			.ok_or_else(|| Error::not_found("Card Id not present"))?;
 
 		let games = self.nodes[*card_key]
 			.links
@@ -221,9 +881,9 @@ impl StoreData {
 	#[instrument(skip(self))]
 	pub fn get_cards_for_game(&self, game_id: &str) -> Result<Vec<MicroSDCard>, Error> {
 		let game_key = self
-			.node_ids
+			.game_ids
 			.get(game_id)
-			.ok_or_else(|| Error::from_str("Game Id not present"))?;
+			.ok_or_else(|| Error::not_found("Game Id not present"))?;
 
 		let cards = self.nodes[*game_key]
 			.links
@@ -235,19 +895,41 @@ impl StoreData {
 	}
 
 	#[instrument(skip(self))]
-	pub fn list_cards(&self) -> Vec<MicroSDCard> {
+	pub fn list_games(&self) -> Vec<Game> {
 		self.nodes
 			.iter()
-			.filter_map(|node| node.1.element.as_card())
+			.filter_map(|node| node.1.element.as_game())
 			.collect()
 	}
 
+	/// Searches card and game names & ids, returning up to `limit` hits ordered from best to worst match
 	#[instrument(skip(self))]
-	pub fn list_games(&self) -> Vec<Game> {
-		self.nodes
-			.iter()
-			.filter_map(|node| node.1.element.as_game())
-			.collect()
+	pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+		let mut hits: Vec<SearchHit> = self
+			.index
+			.search(query)
+			.into_iter()
+			.filter_map(|(id, score)| match id {
+				NodeId::Card(card_id) => Some(SearchHit::Card {
+					score,
+					card: self.get_card(card_id).ok()?,
+				}),
+				NodeId::Game(game_id) => Some(SearchHit::Game {
+					score,
+					game: self.get_game(game_id).ok()?,
+					cards: self.get_cards_for_game(game_id).ok()?,
+				}),
+				NodeId::Group(_) => None,
+			})
+			.collect();
+
+		hits.sort_by(|a, b| {
+			b.score()
+				.cmp(&a.score())
+				.then_with(|| a.name().cmp(b.name()))
+		});
+		hits.truncate(limit);
+		hits
 	}
 
 	#[instrument(skip(self))]
@@ -270,27 +952,738 @@ impl StoreData {
 }
 
 impl StoreData {
-	pub fn delete_hash(&mut self, key: &str) {
-		self.hashes.remove(key);
+	#[instrument(skip(self))]
+	pub fn get_card_stats(&self, card_id: &str) -> Result<CardStats, Error> {
+		let (card, games) = self.get_card_and_games(card_id)?;
+		Ok(CardStats::new(card, &games))
 	}
 
-	pub fn update_hash(&mut self, key: &str, hash: u64) {
-		*self.hashes.entry(key.to_string()).or_insert(0) = hash;
+	#[instrument(skip(self))]
+	/// Archived cards, including those retired by a merge, are left out together with the games only they hold
+	pub fn get_collection_stats(&self) -> CollectionStats {
+		let (archived, active): (Vec<_>, Vec<_>) = self
+			.list_cards_with_games()
+			.into_iter()
+			.partition(|(card, _)| card.archived.is_some());
+
+		let mut per_card: Vec<CardStats> = active
+			.into_iter()
+			.map(|(card, games)| CardStats::new(card, &games))
+			.collect();
+		per_card.sort_by(|a, b| {
+			a.card
+				.position
+				.cmp(&b.card.position)
+				.then_with(|| a.card.uid.cmp(&b.card.uid))
+		});
+
+		let games: Vec<Game> = self
+			.list_games()
+			.into_iter()
+			.filter(|game| {
+				let cards = self.get_cards_for_game(&game.uid).unwrap_or_default();
+				cards.is_empty() || cards.iter().any(|card| card.archived.is_none())
+			})
+			.collect();
+		let steam_games = games.iter().filter(|game| game.source.is_steam()).count();
+
+		CollectionStats {
+			cards: per_card.len(),
+			archived_cards: archived.len(),
+			unique_games: games.len(),
+			steam_games,
+			non_steam_games: games.len() - steam_games,
+			total_size: per_card.iter().map(|stats| stats.total_size).sum(),
+			unique_size: games.iter().map(|game| game.size).sum(),
+			largest_games: largest_games(&games),
+			per_card,
+		}
 	}
+}
 
-	pub fn is_hash_changed(&self, id: &'_ str, mount: &Option<String>) -> Option<u64> {
-		let file_metadata: Vec<_> = get_steam_acf_files(mount)
-			.ok()?
-			.filter_map(|f| fs::metadata(f.path()).ok())
-			.collect();
+impl StoreData {
+	#[instrument(skip(self))]
+	pub fn get_group(&self, group_id: &str) -> Result<Group, Error> {
+		self.group_ids
+			.get(group_id)
+			.map_or(Err(Error::not_found("Group Id not present")), |key| {
+				self.nodes[*key]
+					.element
+					.as_group()
+					.ok_or_else(|| Error::not_found("Id does not belong to a group"))
+			})
+	}
 
-		let mut s = DefaultHasher::new();
+	/// Returns the group together with its cards in the group's order
+	#[instrument(skip(self))]
+	pub fn get_group_and_cards(&self, group_id: &str) -> Result<(Group, Vec<MicroSDCard>), Error> {
+		let group = self.get_group(group_id)?;
 
-		mount.hash(&mut s);
+		let mut cards: Vec<MicroSDCard> = self.nodes[self.group_ids[group_id]]
+			.links
+			.iter()
+			.filter_map(|card_key| self.nodes[*card_key].element.as_card())
+			.collect();
+		group.sort_cards(&mut cards);
 
-		for metadata in file_metadata {
-			metadata.len().hash(&mut s);
-			metadata
+		Ok((group, cards))
+	}
+
+	#[instrument(skip(self))]
+	pub fn get_groups_for_card(&self, card_id: &str) -> Result<Vec<Group>, Error> {
+		let card_key = self
+			.card_ids
+			.get(card_id)
+			.ok_or_else(|| Error::not_found("Card Id not present"))?;
+
+		let mut groups: Vec<Group> = self.nodes[*card_key]
+			.links
+			.iter()
+			.filter_map(|group_key| self.nodes[*group_key].element.as_group())
+			.collect();
+		groups.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.uid.cmp(&b.uid)));
+
+		Ok(groups)
+	}
+
+	/// Lists every group with its cards, sorted by group name
+	#[instrument(skip(self))]
+	pub fn list_groups(&self) -> Vec<(Group, Vec<MicroSDCard>)> {
+		let mut groups: Vec<(Group, Vec<MicroSDCard>)> = self
+			.group_ids
+			.keys()
+			.filter_map(|group_id| self.get_group_and_cards(group_id).ok())
+			.collect();
+		groups.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name).then_with(|| a.uid.cmp(&b.uid)));
+
+		groups
+	}
+}
+
+impl StoreData {
+	/// Lists every tag in use and how many cards & games carry it
+	#[instrument(skip(self))]
+	pub fn list_tags(&self) -> BTreeMap<String, TagUsage> {
+		let mut tags: BTreeMap<String, TagUsage> = BTreeMap::new();
+
+		for node in self.nodes.values() {
+			match node.element {
+				StoreElement::Card(ref card) => card.tags.iter().for_each(|tag| {
+					tags.entry(tag.clone()).or_default().cards += 1;
+				}),
+				StoreElement::Game(ref game) => game.tags.iter().for_each(|tag| {
+					tags.entry(tag.clone()).or_default().games += 1;
+				}),
+				StoreElement::Group(_) => {}
+			}
+		}
+
+		tags
+	}
+
+	/// Builds the operations applying `func` to the tags of every card and game carrying `tag`
+	fn retag(&self, tag: &str, func: impl Fn(&mut BTreeSet<String>)) -> Vec<Operation> {
+		self.nodes
+			.values()
+			.filter_map(|node| match node.element {
+				StoreElement::Card(ref before) if before.tags.contains(tag) => {
+					let mut after = before.clone();
+					func(&mut after.tags);
+					Some(Operation::UpdateCard {
+						before: before.clone(),
+						after,
+					})
+				}
+				StoreElement::Game(ref before) if before.tags.contains(tag) => {
+					let mut after = before.clone();
+					func(&mut after.tags);
+					Some(Operation::UpdateGame {
+						before: before.clone(),
+						after,
+					})
+				}
+				_ => None,
+			})
+			.collect()
+	}
+}
+
+impl StoreData {
+	fn card_sort_values(&self, card: &MicroSDCard, games: &[Game]) -> SortValues {
+		SortValues {
+			name: card.name.clone(),
+			size: games.iter().map(|game| game.size).sum(),
+			position: card.position,
+			last_seen: self.history.get(&card.uid).map(|history| history.last_seen),
+		}
+	}
+
+	/// Games are positioned after the first card they are on and were last seen whenever any card last saw them
+	fn game_sort_values(&self, game: &Game, cards: &[MicroSDCard]) -> SortValues {
+		SortValues {
+			name: game.name.clone(),
+			size: game.size,
+			position: cards
+				.iter()
+				.map(|card| card.position)
+				.min()
+				.unwrap_or(u32::MAX),
+			last_seen: cards
+				.iter()
+				.filter_map(|card| self.history.get(&card.uid)?.games.get(&game.uid))
+				.map(|sighting| sighting.last_seen)
+				.max(),
+		}
+	}
+
+	fn matches_card(query: &ListQuery, card: &MicroSDCard) -> bool {
+		query.card.as_ref().is_none_or(|id| *id == card.uid)
+			&& query.hidden.is_none_or(|hidden| hidden == card.hidden)
+			&& query.archived == card.archived.is_some()
+			&& query.matches_tags(&card.tags)
+	}
+
+	fn matches_game(query: &ListQuery, game: &Game) -> bool {
+		query
+			.is_steam
+			.is_none_or(|is_steam| is_steam == game.source.is_steam())
+			&& query.source.is_none_or(|source| source == game.source)
+			&& query.matches_size(game.size)
+			&& query.matches_name(&game.name)
+	}
+
+	/// Lists cards filtered by id, visibility, name and total size of their games. Sorted by position unless requested otherwise
+	#[instrument(skip(self))]
+	pub fn query_cards(&self, query: &ListQuery) -> Result<Page<MicroSDCard>, Error> {
+		let cards = self
+			.list_cards_with_games()
+			.into_iter()
+			.filter(|(card, games)| {
+				Self::matches_card(query, card)
+					&& query.matches_name(&card.name)
+					&& query.matches_size(games.iter().map(|game| game.size).sum())
+			})
+			.map(|(card, games)| (self.card_sort_values(&card, &games), card))
+			.collect();
+
+		paginate(cards, |card| &card.uid, query, SortKey::Position)
+	}
+
+	/// Lists games filtered by card, origin, name and size. Sorted by name unless requested otherwise
+	#[instrument(skip(self))]
+	pub fn query_games(&self, query: &ListQuery) -> Result<Page<Game>, Error> {
+		if let Some(ref card_id) = query.card {
+			if !self.card_ids.contains_key(card_id) {
+				return Err(Error::not_found("Card Id not present"));
+			}
+		}
+
+		let games = self
+			.list_games()
+			.into_iter()
+			.filter(|game| Self::matches_game(query, game) && query.matches_tags(&game.tags))
+			.map(|game| {
+				let cards = self.get_cards_for_game(&game.uid).unwrap_or_default();
+				(cards, game)
+			})
+			.filter(|(cards, _)| {
+				query
+					.card
+					.as_ref()
+					.is_none_or(|id| cards.iter().any(|card| card.uid == *id))
+			})
+			.map(|(cards, game)| (self.game_sort_values(&game, &cards), game))
+			.collect();
+
+		paginate(games, |game| &game.uid, query, SortKey::Name)
+	}
+
+	/// Lists cards filtered by id & visibility together with their games filtered by origin, name and size.
+	/// Cards are paginated and sorted by position unless requested otherwise, games are sorted by the same key
+	#[instrument(skip(self))]
+	pub fn query_cards_with_games(
+		&self,
+		query: &ListQuery,
+	) -> Result<Page<(MicroSDCard, Vec<Game>)>, Error> {
+		let cards = self
+			.list_cards_with_games()
+			.into_iter()
+			.filter(|(card, _)| Self::matches_card(query, card))
+			.map(|(card, games)| {
+				let games: Vec<(SortValues, Game)> = games
+					.into_iter()
+					.filter(|game| Self::matches_game(query, game))
+					.map(|game| {
+						(
+							self.game_sort_values(&game, std::slice::from_ref(&card)),
+							game,
+						)
+					})
+					.collect();
+
+				let games = paginate(
+					games,
+					|game| &game.uid,
+					&ListQuery {
+						cursor: None,
+						limit: None,
+						..query.clone()
+					},
+					SortKey::Name,
+				)
+				.map(|page| page.items)
+				.unwrap_or_default();
+
+				(self.card_sort_values(&card, &games), (card, games))
+			})
+			.collect();
+
+		paginate(cards, |(card, _)| &card.uid, query, SortKey::Position)
+	}
+}
+
+impl StoreData {
+	/// Reports every integrity issue within the store without modifying it
+	pub fn check_integrity(&self) -> Vec<IntegrityIssue> {
+		self.clone().repair_silently()
+	}
+
+	/// Repairs every integrity issue within the store, logging and returning each fix that was made
+	#[instrument(skip(self))]
+	pub fn repair(&mut self) -> Vec<IntegrityIssue> {
+		let fixes = self.repair_silently();
+		self.rebuild_index();
+
+		if !fixes.is_empty() {
+			self.revision += 1;
+		}
+
+		for fix in &fixes {
+			warn!(?fix, "Repaired store integrity issue");
+		}
+
+		fixes
+	}
+
+	fn repair_silently(&mut self) -> Vec<IntegrityIssue> {
+		let mut fixes = vec![];
+
+		// Ids pointing at nodes that no longer exist, at the wrong type of element or at an element with a different uid are dropped.
+		// Any node left without an id is picked back up as an orphan further down
+		let namespaces = [
+			(&mut self.card_ids, NodeId::Card as fn(String) -> NodeId),
+			(&mut self.game_ids, NodeId::Game),
+			(&mut self.group_ids, NodeId::Group),
+		];
+		for (ids, to_id) in namespaces {
+			ids.retain(|id, key| {
+				let typed_id = to_id(id.clone());
+
+				let Some(node) = self.nodes.get(*key) else {
+					fixes.push(IntegrityIssue::DeadId { id: typed_id });
+					return false;
+				};
+
+				if std::mem::discriminant(&node.element.id()) != std::mem::discriminant(&typed_id) {
+					fixes.push(IntegrityIssue::WrongElementType { id: typed_id });
+					return false;
+				}
+
+				if node.element.uid() != id {
+					fixes.push(IntegrityIssue::MismatchedId {
+						id: typed_id,
+						uid: node.element.uid().to_string(),
+					});
+					return false;
+				}
+
+				true
+			});
+		}
+
+		// Links to missing nodes or between elements which cannot be linked are removed from both sides
+		let mut broken_links: HashSet<(DefaultKey, DefaultKey)> = HashSet::new();
+		for (key, node) in &self.nodes {
+			for link in &node.links {
+				match self.nodes.get(*link) {
+					None => {
+						fixes.push(IntegrityIssue::DanglingLink {
+							uid: node.element.uid().to_string(),
+						});
+						broken_links.insert((key, *link));
+					}
+					Some(other) if !other.element.can_link(&node.element) => {
+						let pair = (key.min(*link), key.max(*link));
+						if broken_links.insert(pair) {
+							fixes.push(IntegrityIssue::InvalidLink {
+								from: node.element.uid().to_string(),
+								to: other.element.uid().to_string(),
+							});
+						}
+					}
+					Some(_) => {}
+				}
+			}
+		}
+		for (a, b) in broken_links {
+			if let Some(node) = self.nodes.get_mut(a) {
+				node.links.remove(&b);
+			}
+			if let Some(node) = self.nodes.get_mut(b) {
+				node.links.remove(&a);
+			}
+		}
+
+		// Links only present on one side are restored on the other
+		let mut one_sided_links = vec![];
+		for (key, node) in &self.nodes {
+			for link in &node.links {
+				if !self.nodes[*link].links.contains(&key) {
+					one_sided_links.push((key, *link));
+				}
+			}
+		}
+		for (from, to) in one_sided_links {
+			self.nodes[to].links.insert(from);
+			fixes.push(IntegrityIssue::AsymmetricLink {
+				from: self.nodes[from].element.uid().to_string(),
+				to: self.nodes[to].element.uid().to_string(),
+			});
+		}
+
+		// Elements sharing a uid are merged into the one the id points at, keeping the union of their links
+		let mut by_id: HashMap<NodeId, Vec<DefaultKey>> = HashMap::new();
+		for (key, node) in &self.nodes {
+			by_id.entry(node.element.id()).or_default().push(key);
+		}
+		for (id, keys) in by_id.into_iter().filter(|(_, keys)| keys.len() > 1) {
+			let primary = self
+				.ids(&id)
+				.get(id.as_str())
+				.copied()
+				.filter(|key| keys.contains(key))
+				.unwrap_or(keys[0]);
+
+			for duplicate in keys.iter().copied().filter(|key| *key != primary) {
+				let links = std::mem::take(&mut self.nodes[duplicate].links);
+				for link in links {
+					self.nodes[link].links.remove(&duplicate);
+					self.nodes[link].links.insert(primary);
+					self.nodes[primary].links.insert(link);
+				}
+				self.nodes.remove(duplicate);
+			}
+
+			fixes.push(IntegrityIssue::DuplicateUid {
+				uid: id.as_str().to_string(),
+				count: keys.len(),
+			});
+		}
+
+		// Nodes without an id are registered under their uid, or removed if that uid is already taken
+		let referenced: HashSet<DefaultKey> = self
+			.card_ids
+			.values()
+			.chain(self.game_ids.values())
+			.chain(self.group_ids.values())
+			.copied()
+			.collect();
+		let orphans: Vec<DefaultKey> = self
+			.nodes
+			.keys()
+			.filter(|key| !referenced.contains(key))
+			.collect();
+		for key in orphans {
+			let id = self.nodes[key].element.id();
+			if self.ids(&id).contains_key(id.as_str()) {
+				self.remove_node(key);
+			} else {
+				self.ids_mut(&id).insert(id.as_str().to_string(), key);
+			}
+			fixes.push(IntegrityIssue::OrphanedNode {
+				uid: id.as_str().to_string(),
+			});
+		}
+
+		fixes
+	}
+}
+
+impl StoreData {
+	/// Works out what merging the source card into the target card changes without modifying anything
+	#[instrument(skip(self))]
+	pub fn plan_merge(
+		&self,
+		source_id: &str,
+		target_id: &str,
+		retire: bool,
+	) -> Result<MergePlan, Error> {
+		if source_id == target_id {
+			return Err(Error::invalid_input("Cannot merge a card into itself"));
+		}
+
+		let (source, source_games) = self.get_card_and_games(source_id)?;
+		let before = self.get_card(target_id)?;
+
+		let after = MicroSDCard {
+			name: source.name.clone(),
+			position: source.position,
+			tags: before.tags.union(&source.tags).cloned().collect(),
+			notes: before.notes.clone().or(source.notes.clone()),
+			..before.clone()
+		};
+
+		// Steam games are picked up by the watcher once the target is inserted
+		let mut games = vec![];
+		for game in source_games
+			.into_iter()
+			.filter(|game| !game.source.is_steam())
+		{
+			if !self.is_linked(&game.uid, target_id)? {
+				games.push(game);
+			}
+		}
+		games.sort_by(|a, b| a.uid.cmp(&b.uid));
+
+		let mut groups = vec![];
+		for group in self.get_groups_for_card(source_id)? {
+			if !self.is_grouped(&group.uid, target_id)? {
+				groups.push(group);
+			}
+		}
+
+		Ok(MergePlan {
+			history: self.history.contains_key(source_id),
+			source,
+			before,
+			after,
+			games,
+			groups,
+			retire,
+		})
+	}
+
+	/// The operation folding the history of the source card into the target card, if the source has any history
+	pub fn merge_history(
+		&self,
+		source_id: &str,
+		target_id: &str,
+		now: DateTime<Utc>,
+	) -> Option<Operation> {
+		let source = self.history.get(source_id)?;
+		let before = self.history.get(target_id).cloned();
+
+		let mut after = before.clone().unwrap_or_else(|| CardHistory::new(now));
+		after.absorb(source);
+
+		Some(Operation::SetHistory {
+			card_id: target_id.to_string(),
+			before,
+			after: Some(after),
+		})
+	}
+
+	/// Every caller changes the history it gets, so this counts as a change of the store. Callers publish [`CardEvent::history`] for it.
+	/// History is only recorded for cards in the store
+	fn history_mut(
+		&mut self,
+		card_id: &str,
+		now: DateTime<Utc>,
+	) -> Result<&mut CardHistory, Error> {
+		if !self.card_ids.contains_key(card_id) {
+			return Err(Error::not_found("Card Id not present"));
+		}
+
+		self.revision += 1;
+		Ok(self
+			.history
+			.entry(card_id.to_string())
+			.or_insert_with(|| CardHistory::new(now)))
+	}
+
+	pub fn card_inserted(&mut self, card_id: &str, now: DateTime<Utc>) -> Result<(), Error> {
+		let games = self.get_games_on_card(card_id)?;
+		let history = self.history_mut(card_id, now)?;
+
+		history.start_session(now);
+		for game in games {
+			history.see_game(&game.uid, None, None, now);
+		}
+		Ok(())
+	}
+
+	pub fn card_removed(&mut self, card_id: &str, now: DateTime<Utc>) -> Result<(), Error> {
+		let games = self.get_games_on_card(card_id)?;
+		let history = self.history_mut(card_id, now)?;
+
+		history.end_session(now);
+		for game in games {
+			history.see_game(&game.uid, None, None, now);
+		}
+		Ok(())
+	}
+
+	/// Records the games read from the card together with their build id, if known, and size on the card
+	pub fn games_seen(
+		&mut self,
+		card_id: &str,
+		games: &[(String, Option<String>, u64)],
+		now: DateTime<Utc>,
+	) -> Result<(), Error> {
+		let history = self.history_mut(card_id, now)?;
+
+		for (game_id, build_id, size) in games {
+			history.see_game(game_id, build_id.as_deref(), Some(*size), now);
+		}
+		Ok(())
+	}
+
+	pub fn game_removed(
+		&mut self,
+		card_id: &str,
+		game: &Game,
+		now: DateTime<Utc>,
+	) -> Result<(), Error> {
+		self.history_mut(card_id, now)?
+			.remove_game(&game.uid, game.size, now);
+		Ok(())
+	}
+
+	/// Lists every card the game was removed from, most recently removed first
+	pub fn get_removed_game(&self, game_id: &str) -> Result<Vec<RemovedGame>, Error> {
+		if !self.game_ids.contains_key(game_id) {
+			return Err(Error::not_found("Game Id not present"));
+		}
+
+		let mut removed: Vec<RemovedGame> = self
+			.history
+			.iter()
+			.filter_map(|(card_id, history)| {
+				let sighting = history.games.get(game_id)?;
+				Some(RemovedGame {
+					card_id: card_id.clone(),
+					card: self.get_card(card_id).ok(),
+					first_seen: sighting.first_seen,
+					tombstone: sighting.tombstone.clone()?,
+				})
+			})
+			.collect();
+		removed.sort_by(|a, b| b.tombstone.removed.cmp(&a.tombstone.removed));
+
+		Ok(removed)
+	}
+
+	pub fn get_card_history(&self, card_id: &str) -> Result<CardHistory, Error> {
+		self.history
+			.get(card_id)
+			.cloned()
+			.ok_or_else(|| Error::not_found("No history present for card"))
+	}
+
+	pub fn card_history(&self, card_id: &str) -> Option<&CardHistory> {
+		self.history.get(card_id)
+	}
+
+	/// Every recorded history, including that of cards which are no longer in the store
+	pub fn all_card_history(&self) -> impl Iterator<Item = (&String, &CardHistory)> {
+		self.history.iter()
+	}
+
+	/// Lists the history of every card that is still in the store
+	pub fn list_card_history(&self) -> HashMap<String, CardHistory> {
+		self.history
+			.iter()
+			.filter(|(card_id, _)| self.card_ids.contains_key(*card_id))
+			.map(|(card_id, history)| (card_id.clone(), history.clone()))
+			.collect()
+	}
+
+	/// Lists every game installed on more than one card, or on a card as well as the internal storage, largest savings first
+	#[instrument(skip(self, internal))]
+	pub fn find_duplicates(
+		&self,
+		internal: &HashMap<String, InternalInstall>,
+	) -> Vec<DuplicateGame> {
+		let mut duplicates: Vec<DuplicateGame> = self
+			.list_games()
+			.into_iter()
+			.filter_map(|game| {
+				let cards: Vec<CardInstall> = self
+					.get_cards_for_game(&game.uid)
+					.ok()?
+					.into_iter()
+					.map(|card| {
+						let sighting = self
+							.history
+							.get(&card.uid)
+							.and_then(|history| history.games.get(&game.uid));
+
+						CardInstall {
+							build_id: sighting.and_then(|sighting| sighting.build_id.clone()),
+							size: sighting
+								.and_then(|sighting| sighting.size)
+								.unwrap_or(game.size),
+							last_seen: sighting.map(|sighting| sighting.last_seen),
+							card,
+						}
+					})
+					.collect();
+
+				let internal = internal.get(&game.uid).cloned();
+
+				let sizes: Vec<u64> = cards
+					.iter()
+					.map(|install| install.size)
+					.chain(internal.iter().map(|install| install.size))
+					.collect();
+
+				if sizes.len() < 2 {
+					return None;
+				}
+
+				let reclaimable =
+					sizes.iter().sum::<u64>() - sizes.iter().max().copied().unwrap_or_default();
+
+				Some(DuplicateGame {
+					game,
+					cards,
+					internal,
+					reclaimable,
+				})
+			})
+			.collect();
+
+		duplicates.sort_by(|a, b| {
+			b.reclaimable
+				.cmp(&a.reclaimable)
+				.then_with(|| a.game.uid.cmp(&b.game.uid))
+		});
+		duplicates
+	}
+}
+
+impl StoreData {
+	pub fn delete_hash(&mut self, key: &str) {
+		self.hashes.remove(key);
+	}
+
+	pub fn update_hash(&mut self, key: &str, hash: u64) {
+		*self.hashes.entry(key.to_string()).or_insert(0) = hash;
+	}
+
+	pub fn is_hash_changed(&self, id: &'_ str, mount: &Option<String>) -> Option<u64> {
+		let file_metadata: Vec<_> = get_steam_acf_files(mount)
+			.ok()?
+			.filter_map(|f| fs::metadata(f.path()).ok())
+			.collect();
+
+		let mut s = DefaultHasher::new();
+
+		mount.hash(&mut s);
+
+		for metadata in file_metadata {
+			metadata.len().hash(&mut s);
+			metadata
 				.modified()
 				.expect("Last Modified time to exist")
 				.hash(&mut s);
@@ -313,31 +1706,107 @@ impl StoreData {
 	}
 }
 
+fn same_ids<'a>(ids: &[String], other: impl Iterator<Item = &'a String>) -> bool {
+	let ids: HashSet<&String> = ids.iter().collect();
+	let other: HashSet<&String> = other.collect();
+	ids == other
+}
+
+/// Elements whose own state or links are changed by the operation
+fn affected_elements(operation: &Operation) -> Vec<NodeId> {
+	match operation {
+		Operation::AddCard {
+			card,
+			games,
+			groups,
+		}
+		| Operation::RemoveCard {
+			card,
+			games,
+			groups,
+		} => std::iter::once(NodeId::Card(card.uid.clone()))
+			.chain(games.iter().cloned().map(NodeId::Game))
+			.chain(groups.iter().cloned().map(NodeId::Group))
+			.collect(),
+		Operation::UpdateCard { after, .. } => vec![NodeId::Card(after.uid.clone())],
+		Operation::AddGame { game, cards } | Operation::RemoveGame { game, cards } => {
+			std::iter::once(NodeId::Game(game.uid.clone()))
+				.chain(cards.iter().cloned().map(NodeId::Card))
+				.collect()
+		}
+		Operation::UpdateGame { after, .. } => vec![NodeId::Game(after.uid.clone())],
+		Operation::AddGroup { group, cards } | Operation::RemoveGroup { group, cards } => {
+			std::iter::once(NodeId::Group(group.uid.clone()))
+				.chain(cards.iter().cloned().map(NodeId::Card))
+				.collect()
+		}
+		Operation::UpdateGroup { after, .. } => vec![NodeId::Group(after.uid.clone())],
+		Operation::AddToGroup { group_id, card_id }
+		| Operation::RemoveFromGroup { group_id, card_id } => vec![
+			NodeId::Group(group_id.clone()),
+			NodeId::Card(card_id.clone()),
+		],
+		Operation::Link { game_id, card_id } | Operation::Unlink { game_id, card_id } => {
+			vec![NodeId::Game(game_id.clone()), NodeId::Card(card_id.clone())]
+		}
+		// History is bookkeeping next to the card rather than part of it
+		Operation::SetHistory { .. } => vec![],
+	}
+}
+
+fn journal_file(file: &Option<PathBuf>) -> Option<PathBuf> {
+	file.as_ref().map(|file| file.with_extension("journal"))
+}
+
+/// Number of events retained for clients catching up after reconnecting
+const EVENT_CAPACITY: usize = 256;
+
 #[derive(Debug)]
 pub struct Store {
 	data: RwLock<StoreData>,
+	journal: RwLock<Journal>,
+	events: EventLog<CardEvent>,
 	file: Option<PathBuf>,
 }
 
 impl Store {
 	pub fn new(file: Option<PathBuf>) -> Self {
 		Store {
-			data: RwLock::new(StoreData {
-				version: Version::parse(PACKAGE_VERSION).unwrap(),
-				nodes: SlotMap::new(),
-				node_ids: HashMap::new(),
-				hashes: HashMap::new(),
-			}),
+			journal: RwLock::new(Journal::new(journal_file(&file))),
+			data: RwLock::new(StoreData::new()),
+			events: EventLog::new(EVENT_CAPACITY, 0),
 			file,
 		}
 	}
 
 	pub fn read_from_file(file: PathBuf) -> Result<Self, Error> {
 		let contents = read_to_string(&file).map_err(Error::from)?;
-		let store_data: StoreData = serde_json::from_str(&contents).map_err(Error::from)?;
+		let value: serde_json::Value = serde_json::from_str(&contents).map_err(Error::from)?;
+
+		// Stores written before the canonical format serialized the graph itself.
+		// The first write replaces the file in the new format, so the original is kept next to it
+		let mut store_data: StoreData = match value.get("nodes") {
+			Some(_) => {
+				let backup = file.with_extension("legacy.bak");
+				info!(
+					backup = backup.to_str(),
+					"Converting store from the legacy graph layout"
+				);
+				fs::copy(&file, &backup).map_err(Error::from)?;
+				serde_json::from_value(value).map_err(Error::from)?
+			}
+			None => serde_json::from_value::<StoreFile>(value)
+				.map_err(Error::from)?
+				.into_data(),
+		};
 		debug!("Loaded datastore v {}", store_data.version);
+		store_data.migrate();
+		store_data.rebuild_index();
+		store_data.reset_revisions();
 		Ok(Store {
+			events: EventLog::new(EVENT_CAPACITY, store_data.revision),
 			data: RwLock::new(store_data),
+			journal: RwLock::new(Journal::read_from_file(file.with_extension("journal"))),
 			file: Some(file),
 		})
 	}
@@ -352,7 +1821,7 @@ impl Store {
 			self.file
 				.as_ref()
 				.ok_or(Error::from_str("No Path specified"))?,
-			serde_json::to_string(&self.data)?,
+			serde_json::to_string_pretty(&StoreFile::from_data(&self.data.read().unwrap()))?,
 		)?;
 		Ok(())
 	}
@@ -368,26 +1837,41 @@ impl Store {
 	}
 
 	pub fn validate(&self) -> bool {
-		let data = self.data.read().unwrap();
+		let issues = self.check_integrity();
 
-		let mut result = true;
+		for issue in &issues {
+			error!(?issue, "Found store integrity issue");
+		}
 
-		{
-			let mut dead_node_ids: Vec<(&String, &DefaultKey)> = vec![];
+		issues.is_empty()
+	}
 
-			for pair in &data.node_ids {
-				if !data.nodes.contains_key(*pair.1) {
-					dead_node_ids.push(pair);
-				}
-			}
+	pub fn check_integrity(&self) -> Vec<IntegrityIssue> {
+		self.data.read().unwrap().check_integrity()
+	}
 
-			if !dead_node_ids.is_empty() {
-				result &= false;
-				error!(?dead_node_ids, "Found dead node_ids");
+	pub fn repair(&self) -> Vec<IntegrityIssue> {
+		let fixes = {
+			let mut data = self.data.write().unwrap();
+			let fixes = data.repair();
+			if !fixes.is_empty() {
+				// Repairs are not journaled, so clients are only told that anything may have changed
+				self.events
+					.publish(data.revision, CardEvent::new(ChangeKind::Updated));
 			}
+			fixes
+		};
+
+		if !fixes.is_empty() {
+			info!(
+				count = fixes.len(),
+				"Repaired {} store integrity issues",
+				fixes.len()
+			);
+			self.try_write_to_file();
 		}
 
-		result
+		fixes
 	}
 
 	/// cleans up any data to make it consistent with what we expect.
@@ -395,67 +1879,703 @@ impl Store {
 		let mut data = self.data.write().unwrap();
 
 		// Removes any whitespace from the card uid
-		let cleaned_node_ids: HashMap<String, DefaultKey> = data
-			.node_ids
+		let cleaned_card_ids: HashMap<String, DefaultKey> = data
+			.card_ids
 			.iter()
 			.map(|f| (f.0.trim().to_string(), *f.1))
 			.collect();
 
-		data.node_ids = cleaned_node_ids;
+		data.card_ids = cleaned_card_ids;
 
 		for node in data.nodes.borrow_mut() {
 			match node.1.element {
 				StoreElement::Card(ref mut card) => {
 					card.uid = card.uid.trim().to_string();
 				}
-				StoreElement::Game(_) => {}
+				StoreElement::Game(_) | StoreElement::Group(_) => {}
 			}
 		}
+
+		data.rebuild_index();
 	}
 
-	pub fn add_card(&self, id: String, card: MicroSDCard) {
-		self.data.write().unwrap().add_card(id, card);
-		self.try_write_to_file()
+	/// Applies and journals the operations produced by `build`, which sees the store under the same lock.
+	/// Producing no operations leaves the store untouched
+	fn commit<F>(&self, origin: Origin, build: F) -> Result<(), Error>
+	where
+		F: FnOnce(&StoreData) -> Result<Vec<Operation>, Error>,
+	{
+		// the lock has to be released before try_write_to_file otherwise it causes a deadlock
+		{
+			let mut data = self.data.write().unwrap();
+			let operations = build(&data)?;
+
+			if operations.is_empty() {
+				return Ok(());
+			}
+
+			let operations = operations.into_iter().map(|operation| (operation, None));
+			self.apply_all(&mut data, origin, operations.collect())?;
+		}
+		self.try_write_to_file();
+		Ok(())
+	}
+
+	/// Applies the operations of a single change. Should one of them fail, the ones applied before it are reverted again
+	/// so that either all of them apply or none does. The operations are then published & journaled together
+	fn apply_all(
+		&self,
+		data: &mut StoreData,
+		origin: Origin,
+		operations: Vec<(Operation, Option<u64>)>,
+	) -> Result<Vec<JournalEntry>, Error> {
+		let mut revisions = vec![];
+		for (index, (operation, _)) in operations.iter().enumerate() {
+			if let Err(err) = data.apply(operation) {
+				// Operations only change the store once they are known to apply, so only the previous ones are reverted
+				for (applied, _) in operations[..index].iter().rev() {
+					if let Err(err) = data.apply(&applied.inverse()) {
+						error!(%err, ?applied, "Failed to revert operation of a failed change");
+					}
+				}
+				return Err(err);
+			}
+			revisions.push(data.revision);
+		}
+
+		for ((operation, _), revision) in operations.iter().zip(revisions) {
+			self.events.publish(revision, operation.event());
+		}
+		Ok(self.journal.write().unwrap().record(origin, operations))
+	}
+
+	pub fn add_card(&self, origin: Origin, card: MicroSDCard) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			Ok(
+				match data.contains_element(&NodeId::Card(card.uid.clone())) {
+					true => vec![],
+					false => vec![Operation::AddCard {
+						card,
+						games: vec![],
+						groups: vec![],
+					}],
+				},
+			)
+		})
+	}
+
+	/// Adds the games in one go, either all of them or none. Adding a game again is fine as long as nothing differs, changes
+	/// have to be made through `update_game`. With `keep_existing` games which already exist are left as they are instead
+	pub fn add_games(
+		&self,
+		origin: Origin,
+		games: &[Game],
+		keep_existing: bool,
+	) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			let mut added: HashMap<&String, &Game> = HashMap::new();
+			let mut operations = vec![];
+			for game in games {
+				let existing = match added.get(&game.uid) {
+					Some(existing) => Some((*existing).clone()),
+					None => data.get_game(&game.uid).ok(),
+				};
+
+				match existing {
+					None => {
+						added.insert(&game.uid, game);
+						operations.push(Operation::AddGame {
+							game: game.clone(),
+							cards: vec![],
+						});
+					}
+					Some(existing) if keep_existing || existing == *game => {}
+					Some(_) => {
+						return Err(Error::conflict(&format!(
+							"Game \"{}\" already exists with different fields",
+							game.uid
+						)))
+					}
+				}
+			}
+			Ok(operations)
+		})
 	}
 
-	pub fn add_game(&self, id: String, game: Game) {
-		self.data.write().unwrap().add_game(id, game);
-		self.try_write_to_file()
+	/// Adds the cards which do not exist yet and merges the others into the existing cards, see [`MicroSDCard::merge`].
+	/// Either every card changes or none does
+	pub fn upsert_cards(&self, origin: Origin, cards: &[MicroSDCard]) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			// Cards as they are after the operations so far, a card may be sent more than once
+			let mut pending: HashMap<&String, MicroSDCard> = HashMap::new();
+			let mut operations = vec![];
+			for card in cards {
+				let existing = match pending.get(&card.uid) {
+					Some(existing) => Some(existing.clone()),
+					None => data.get_card(&card.uid).ok(),
+				};
+
+				match existing {
+					Some(before) => {
+						let mut after = before.clone();
+						after.merge(card)?;
+						if before != after {
+							pending.insert(&card.uid, after.clone());
+							operations.push(Operation::UpdateCard { before, after });
+						}
+					}
+					None => {
+						pending.insert(&card.uid, card.clone());
+						operations.push(Operation::AddCard {
+							card: card.clone(),
+							games: vec![],
+							groups: vec![],
+						});
+					}
+				}
+			}
+			Ok(operations)
+		})
 	}
 
-	pub fn update_card<F>(&self, card_id: &str, func: F) -> Result<(), Error>
+	/// Changes the card through `func`. With `expected` the card has to still be at that revision
+	pub fn update_card<F>(
+		&self,
+		origin: Origin,
+		card_id: &str,
+		expected: Option<&[u64]>,
+		mut func: F,
+	) -> Result<(), Error>
 	where
 		F: FnMut(&mut MicroSDCard) -> Result<(), Error>,
 	{
-		self.data.write().unwrap().update_card(card_id, func)?;
-		self.try_write_to_file();
-		Ok(())
+		self.commit(origin, |data| {
+			data.check_revision(&NodeId::Card(card_id.to_string()), expected)?;
+			let before = data.get_card(card_id)?;
+			let mut after = before.clone();
+			func(&mut after)?;
+
+			Ok(match before == after {
+				true => vec![],
+				false => vec![Operation::UpdateCard { before, after }],
+			})
+		})
 	}
 
-	pub fn link(&self, a_id: &str, b_id: &str) -> Result<(), Error> {
-		self.data.write().unwrap().link(a_id, b_id)?;
-		self.try_write_to_file();
-		Ok(())
+	/// Changes several cards through `func` in one go. Either every card changes or none does
+	pub fn update_cards<F>(
+		&self,
+		origin: Origin,
+		card_ids: &[String],
+		mut func: F,
+	) -> Result<(), Error>
+	where
+		F: FnMut(&mut MicroSDCard) -> Result<(), Error>,
+	{
+		self.commit(origin, |data| {
+			let mut operations = vec![];
+			for card_id in card_ids {
+				let before = data.get_card(card_id)?;
+				let mut after = before.clone();
+				func(&mut after)?;
+
+				if before != after {
+					operations.push(Operation::UpdateCard { before, after });
+				}
+			}
+			Ok(operations)
+		})
 	}
 
-	pub fn unlink(&self, a_id: &str, b_id: &str) -> Result<(), Error> {
-		self.data.write().unwrap().unlink(a_id, b_id)?;
-		self.try_write_to_file();
-		Ok(())
+	/// Changes the game through `func`. With `expected` the game has to still be at that revision
+	pub fn update_game<F>(
+		&self,
+		origin: Origin,
+		game_id: &str,
+		expected: Option<&[u64]>,
+		mut func: F,
+	) -> Result<(), Error>
+	where
+		F: FnMut(&mut Game) -> Result<(), Error>,
+	{
+		self.commit(origin, |data| {
+			data.check_revision(&NodeId::Game(game_id.to_string()), expected)?;
+			let before = data.get_game(game_id)?;
+			let mut after = before.clone();
+			func(&mut after)?;
+
+			Ok(match before == after {
+				true => vec![],
+				false => vec![Operation::UpdateGame { before, after }],
+			})
+		})
 	}
 
-	pub fn remove_element(&self, id: &str) -> Result<(), Error> {
-		// these two operations have to happen within a single lock otherwise the try_write_to_file causes a deadlock
-		{
-			let mut lock = self.data.write().unwrap();
-			lock.remove_item(id)?;
-			lock.delete_hash(id);
+	/// Archives the card, keeping it and its links around for later reference
+	pub fn archive_card(
+		&self,
+		origin: Origin,
+		card_id: &str,
+		reason: Option<String>,
+	) -> Result<(), Error> {
+		self.update_card(origin, card_id, None, |card| {
+			if card.archived.is_some() {
+				return Err(Error::conflict("Card is already archived"));
+			}
+
+			card.archived = Some(Archived {
+				date: Utc::now(),
+				reason: reason.clone(),
+			});
+			Ok(())
+		})
+	}
+
+	pub fn restore_card(&self, origin: Origin, card_id: &str) -> Result<(), Error> {
+		self.update_card(origin, card_id, None, |card| match card.archived.take() {
+			Some(_) => Ok(()),
+			None => Err(Error::conflict("Card is not archived")),
+		})
+	}
+
+	pub fn export(&self) -> Export {
+		Export::from_store(&self.data.read().unwrap(), Utc::now())
+	}
+
+	/// Imports a document according to the strategy. The whole import is checked up front, so it either applies completely or not at all.
+	/// With `dry_run` only the report is returned
+	pub fn import(
+		&self,
+		origin: Origin,
+		incoming: &Export,
+		strategy: ImportStrategy,
+		dry_run: bool,
+	) -> Result<ImportReport, Error> {
+		if dry_run {
+			let plan = plan_import(&self.data.read().unwrap(), incoming, strategy)?;
+			return Ok(ImportReport {
+				dry_run: true,
+				..plan.report
+			});
+		}
+
+		let mut result = None;
+		self.commit(origin, |data| {
+			let plan = plan_import(data, incoming, strategy)?;
+			result = Some(plan.report);
+			Ok(plan.operations)
+		})?;
+
+		result.ok_or_else(|| Error::from_str("Import was not planned"))
+	}
+
+	/// Merges the source card into the target card, optionally archiving the source. With `dry_run` only the plan is returned
+	pub fn merge_cards(
+		&self,
+		origin: Origin,
+		source_id: &str,
+		target_id: &str,
+		retire: bool,
+		dry_run: bool,
+	) -> Result<MergePlan, Error> {
+		if dry_run {
+			return self
+				.data
+				.read()
+				.unwrap()
+				.plan_merge(source_id, target_id, retire);
 		}
+
+		let mut result = None;
+		self.commit(origin, |data| {
+			let plan = data.plan_merge(source_id, target_id, retire)?;
+
+			let mut operations = vec![];
+			if plan.before != plan.after {
+				operations.push(Operation::UpdateCard {
+					before: plan.before.clone(),
+					after: plan.after.clone(),
+				});
+			}
+			operations.extend(plan.games.iter().map(|game| Operation::Link {
+				game_id: game.uid.clone(),
+				card_id: target_id.to_string(),
+			}));
+			operations.extend(plan.groups.iter().map(|group| Operation::AddToGroup {
+				group_id: group.uid.clone(),
+				card_id: target_id.to_string(),
+			}));
+			if retire && plan.source.archived.is_none() {
+				operations.push(Operation::UpdateCard {
+					before: plan.source.clone(),
+					after: MicroSDCard {
+						archived: Some(Archived::merged_into(target_id, Utc::now())),
+						..plan.source.clone()
+					},
+				});
+			}
+
+			operations.extend(data.merge_history(source_id, target_id, Utc::now()));
+
+			result = Some(plan);
+			Ok(operations)
+		})?;
+
+		result.ok_or_else(|| Error::from_str("Merge was not planned"))
+	}
+
+	pub fn add_group(&self, origin: Origin, group: Group) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			Ok(
+				match data.contains_element(&NodeId::Group(group.uid.clone())) {
+					true => vec![],
+					false => vec![Operation::AddGroup {
+						group,
+						cards: vec![],
+					}],
+				},
+			)
+		})
+	}
+
+	pub fn rename_group(&self, origin: Origin, group_id: &str, name: &str) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			let before = data.get_group(group_id)?;
+			let after = Group {
+				name: name.to_string(),
+				..before.clone()
+			};
+
+			Ok(match before == after {
+				true => vec![],
+				false => vec![Operation::UpdateGroup { before, after }],
+			})
+		})
+	}
+
+	/// Adds the card to the group, or moves it if it already is a member. Without a position the card is placed last
+	pub fn add_to_group(
+		&self,
+		origin: Origin,
+		group_id: &str,
+		card_id: &str,
+		position: Option<usize>,
+	) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			let mut operations = vec![];
+
+			if !data.is_grouped(group_id, card_id)? {
+				operations.push(Operation::AddToGroup {
+					group_id: group_id.to_string(),
+					card_id: card_id.to_string(),
+				});
+			}
+
+			let (before, cards) = data.get_group_and_cards(group_id)?;
+			let mut order: Vec<String> = cards
+				.into_iter()
+				.map(|card| card.uid)
+				.filter(|uid| uid != card_id)
+				.collect();
+			let position = position.unwrap_or(order.len()).min(order.len());
+			order.insert(position, card_id.to_string());
+
+			let after = Group {
+				order,
+				..before.clone()
+			};
+			if before != after {
+				operations.push(Operation::UpdateGroup { before, after });
+			}
+
+			Ok(operations)
+		})
+	}
+
+	pub fn remove_from_group(
+		&self,
+		origin: Origin,
+		group_id: &str,
+		card_id: &str,
+	) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			if !data.is_grouped(group_id, card_id)? {
+				return Ok(vec![]);
+			}
+
+			let before = data.get_group(group_id)?;
+			let after = Group {
+				order: before
+					.order
+					.iter()
+					.filter(|uid| *uid != card_id)
+					.cloned()
+					.collect(),
+				..before.clone()
+			};
+
+			let mut operations = vec![Operation::RemoveFromGroup {
+				group_id: group_id.to_string(),
+				card_id: card_id.to_string(),
+			}];
+			if before != after {
+				operations.push(Operation::UpdateGroup { before, after });
+			}
+
+			Ok(operations)
+		})
+	}
+
+	/// Replaces the order of the cards within the group. The order has to contain every member exactly once
+	pub fn reorder_group(
+		&self,
+		origin: Origin,
+		group_id: &str,
+		order: Vec<String>,
+	) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			let (before, cards) = data.get_group_and_cards(group_id)?;
+
+			let unique: HashSet<&String> = order.iter().collect();
+			if unique.len() != order.len() || !same_ids(&order, cards.iter().map(|card| &card.uid))
+			{
+				return Err(Error::invalid_input(
+					"Order has to contain every card of the group exactly once",
+				));
+			}
+
+			let after = Group {
+				order,
+				..before.clone()
+			};
+
+			Ok(match before == after {
+				true => vec![],
+				false => vec![Operation::UpdateGroup { before, after }],
+			})
+		})
+	}
+
+	/// Renames a tag on every card and game carrying it
+	pub fn rename_tag(&self, origin: Origin, tag: &str, new_tag: &str) -> Result<(), Error> {
+		if tag == new_tag {
+			return Ok(());
+		}
+
+		self.commit(origin, |data| {
+			Ok(data.retag(tag, |tags| {
+				tags.remove(tag);
+				tags.insert(new_tag.to_string());
+			}))
+		})
+	}
+
+	/// Removes a tag from every card and game carrying it
+	pub fn delete_tag(&self, origin: Origin, tag: &str) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			Ok(data.retag(tag, |tags| {
+				tags.remove(tag);
+			}))
+		})
+	}
+
+	pub fn link(&self, origin: Origin, game_id: &str, card_id: &str) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			Ok(match data.is_linked(game_id, card_id)? {
+				true => vec![],
+				false => vec![Operation::Link {
+					game_id: game_id.to_string(),
+					card_id: card_id.to_string(),
+				}],
+			})
+		})
+	}
+
+	/// Adds or refreshes the games read from the card and links them to it, all in one change
+	pub fn games_scanned(
+		&self,
+		origin: Origin,
+		card_id: &str,
+		scanned: &[Game],
+	) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			let mut operations = vec![];
+			for game in scanned {
+				if !data.contains_element(&NodeId::Game(game.uid.clone())) {
+					operations.push(Operation::AddGame {
+						game: game.clone(),
+						cards: vec![card_id.to_string()],
+					});
+					continue;
+				}
+
+				// Games get renamed & grow with updates, but only fields owned by the watcher are refreshed
+				let before = data.get_game(&game.uid)?;
+				let mut after = before.clone();
+				after.rescanned(game);
+				if before != after {
+					operations.push(Operation::UpdateGame { before, after });
+				}
+
+				if !data.is_linked(&game.uid, card_id)? {
+					operations.push(Operation::Link {
+						game_id: game.uid.clone(),
+						card_id: card_id.to_string(),
+					});
+				}
+			}
+			Ok(operations)
+		})
+	}
+
+	pub fn unlink(&self, origin: Origin, game_id: &str, card_id: &str) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			Ok(match data.is_linked(game_id, card_id)? {
+				false => vec![],
+				true => vec![Operation::Unlink {
+					game_id: game_id.to_string(),
+					card_id: card_id.to_string(),
+				}],
+			})
+		})
+	}
+
+	/// Links the games to the card in one go. With `expected` the card has to still be at that revision
+	pub fn link_games(
+		&self,
+		origin: Origin,
+		card_id: &str,
+		game_ids: &[String],
+		expected: Option<&[u64]>,
+	) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			data.check_revision(&NodeId::Card(card_id.to_string()), expected)?;
+
+			let mut operations = vec![];
+			for game_id in game_ids {
+				if !data.is_linked(game_id, card_id)? {
+					operations.push(Operation::Link {
+						game_id: game_id.clone(),
+						card_id: card_id.to_string(),
+					});
+				}
+			}
+			Ok(operations)
+		})
+	}
+
+	/// Unlinks the games from the card in one go. With `expected` the card has to still be at that revision
+	pub fn unlink_games(
+		&self,
+		origin: Origin,
+		card_id: &str,
+		game_ids: &[String],
+		expected: Option<&[u64]>,
+	) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			data.check_revision(&NodeId::Card(card_id.to_string()), expected)?;
+
+			let mut operations = vec![];
+			for game_id in game_ids {
+				if data.is_linked(game_id, card_id)? {
+					operations.push(Operation::Unlink {
+						game_id: game_id.clone(),
+						card_id: card_id.to_string(),
+					});
+				}
+			}
+			Ok(operations)
+		})
+	}
+
+	pub fn remove_element(&self, origin: Origin, id: &NodeId) -> Result<(), Error> {
+		self.commit(origin, |data| {
+			Ok(vec![match id {
+				NodeId::Card(card_id) => {
+					let (card, games) = data.get_card_and_games(card_id)?;
+					Operation::RemoveCard {
+						card,
+						games: games.into_iter().map(|game| game.uid).collect(),
+						groups: data
+							.get_groups_for_card(card_id)?
+							.into_iter()
+							.map(|group| group.uid)
+							.collect(),
+					}
+				}
+				NodeId::Group(group_id) => {
+					let (group, cards) = data.get_group_and_cards(group_id)?;
+					Operation::RemoveGroup {
+						group,
+						cards: cards.into_iter().map(|card| card.uid).collect(),
+					}
+				}
+				NodeId::Game(game_id) => Operation::RemoveGame {
+					game: data.get_game(game_id)?,
+					cards: data
+						.get_cards_for_game(game_id)?
+						.into_iter()
+						.map(|card| card.uid)
+						.collect(),
+				},
+			}])
+		})
+	}
+
+	/// Returns up to `limit` journal entries, newest first
+	pub fn list_journal(&self, limit: usize) -> Vec<JournalEntry> {
+		self.journal.read().unwrap().recent(limit)
+	}
+
+	/// Reverts the newest `count` changes which have not been undone yet, stopping at the first one that is no longer consistent
+	pub fn undo_last(&self, origin: Origin, count: usize) -> Result<Vec<JournalEntry>, Error> {
+		let ids = self.journal.read().unwrap().undoable(count);
+
+		let mut reverted = vec![];
+		for id in ids {
+			match self.undo(origin, id) {
+				Ok(entries) => reverted.extend(entries),
+				Err(err) if reverted.is_empty() => return Err(err),
+				// The changes reverted so far stay reverted, so they are still reported
+				Err(err) => {
+					warn!(%err, id, "Stopped undoing at a change which is no longer consistent");
+					break;
+				}
+			}
+		}
+
+		Ok(reverted)
+	}
+
+	/// Reverts the change a journal entry is part of, if it is still consistent with the store. Either every operation of the change is reverted or none is
+	pub fn undo(&self, origin: Origin, id: u64) -> Result<Vec<JournalEntry>, Error> {
+		let entries = {
+			let mut data = self.data.write().unwrap();
+			let operations: Vec<(Operation, Option<u64>)> = {
+				let journal = self.journal.read().unwrap();
+
+				if journal.get(id).is_none() {
+					return Err(Error::not_found("Journal entry not present"));
+				}
+				if journal.is_undone(id) {
+					return Err(Error::conflict("Journal entry has already been undone"));
+				}
+
+				journal
+					.unit(id)
+					.into_iter()
+					.rev()
+					.map(|entry| (entry.operation.inverse(), Some(entry.id)))
+					.collect()
+			};
+
+			self.apply_all(&mut data, origin, operations)?
+		};
 		self.try_write_to_file();
-		Ok(())
+		Ok(entries)
 	}
 
-	pub fn contains_element(&self, id: &str) -> bool {
+	pub fn contains_element(&self, id: &NodeId) -> bool {
 		self.data.read().unwrap().contains_element(id)
 	}
 
@@ -463,8 +2583,22 @@ impl Store {
 		self.data.read().unwrap().get_card(card_id)
 	}
 
-	pub fn get_game(&self, game_id: &str) -> Result<Game, Error> {
-		self.data.read().unwrap().get_game(game_id)
+	/// The card together with its revision, for clients to base changes on
+	pub fn get_card_with_revision(&self, card_id: &str) -> Result<(MicroSDCard, u64), Error> {
+		let data = self.data.read().unwrap();
+		Ok((
+			data.get_card(card_id)?,
+			data.revision_of(&NodeId::Card(card_id.to_string()))?,
+		))
+	}
+
+	/// The game together with its revision, for clients to base changes on
+	pub fn get_game_with_revision(&self, game_id: &str) -> Result<(Game, u64), Error> {
+		let data = self.data.read().unwrap();
+		Ok((
+			data.get_game(game_id)?,
+			data.revision_of(&NodeId::Game(game_id.to_string()))?,
+		))
 	}
 
 	pub fn get_card_and_games(&self, card_id: &str) -> Result<(MicroSDCard, Vec<Game>), Error> {
@@ -479,16 +2613,47 @@ impl Store {
 		self.data.read().unwrap().get_cards_for_game(game_id)
 	}
 
-	pub fn list_cards(&self) -> Vec<MicroSDCard> {
-		self.data.read().unwrap().list_cards()
+	pub fn list_tags(&self) -> BTreeMap<String, TagUsage> {
+		self.data.read().unwrap().list_tags()
 	}
 
-	pub fn list_games(&self) -> Vec<Game> {
-		self.data.read().unwrap().list_games()
+	pub fn get_group_and_cards(&self, group_id: &str) -> Result<(Group, Vec<MicroSDCard>), Error> {
+		self.data.read().unwrap().get_group_and_cards(group_id)
 	}
 
-	pub fn list_cards_with_games(&self) -> Vec<(MicroSDCard, Vec<Game>)> {
-		self.data.read().unwrap().list_cards_with_games()
+	pub fn get_groups_for_card(&self, card_id: &str) -> Result<Vec<Group>, Error> {
+		self.data.read().unwrap().get_groups_for_card(card_id)
+	}
+
+	pub fn list_groups(&self) -> Vec<(Group, Vec<MicroSDCard>)> {
+		self.data.read().unwrap().list_groups()
+	}
+
+	pub fn get_card_stats(&self, card_id: &str) -> Result<CardStats, Error> {
+		self.data.read().unwrap().get_card_stats(card_id)
+	}
+
+	pub fn get_collection_stats(&self) -> CollectionStats {
+		self.data.read().unwrap().get_collection_stats()
+	}
+
+	pub fn query_cards(&self, query: &ListQuery) -> Result<Page<MicroSDCard>, Error> {
+		self.data.read().unwrap().query_cards(query)
+	}
+
+	pub fn query_games(&self, query: &ListQuery) -> Result<Page<Game>, Error> {
+		self.data.read().unwrap().query_games(query)
+	}
+
+	pub fn query_cards_with_games(
+		&self,
+		query: &ListQuery,
+	) -> Result<Page<(MicroSDCard, Vec<Game>)>, Error> {
+		self.data.read().unwrap().query_cards_with_games(query)
+	}
+
+	pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+		self.data.read().unwrap().search(query, limit)
 	}
 
 	pub fn is_hash_changed(&self, key: &str, mount: &Option<String>) -> Option<u64> {
@@ -498,4 +2663,312 @@ impl Store {
 	pub fn update_hash(&self, key: &str, hash: u64) {
 		self.data.write().unwrap().update_hash(key, hash)
 	}
+
+	/// Forgets the hash of the card's library so the watcher rereads it on its next cycle
+	pub fn forget_hash(&self, card_id: &str) -> Result<(), Error> {
+		let mut data = self.data.write().unwrap();
+		data.get_card(card_id)?;
+		data.delete_hash(card_id);
+		Ok(())
+	}
+
+	pub fn card_inserted(&self, card_id: &str) -> Result<(), Error> {
+		{
+			let mut data = self.data.write().unwrap();
+			data.card_inserted(card_id, Utc::now())?;
+			self.events.publish(
+				data.revision,
+				CardEvent::card(ChangeKind::Inserted, card_id),
+			);
+		}
+		self.try_write_to_file();
+		Ok(())
+	}
+
+	pub fn card_removed(&self, card_id: &str) -> Result<(), Error> {
+		{
+			let mut data = self.data.write().unwrap();
+			data.card_removed(card_id, Utc::now())?;
+			self.events
+				.publish(data.revision, CardEvent::card(ChangeKind::Removed, card_id));
+		}
+		self.try_write_to_file();
+		Ok(())
+	}
+
+	pub fn revision(&self) -> u64 {
+		self.data.read().unwrap().revision
+	}
+
+	/// Subscribes to changes of the store, replaying those made after the revision `last_id`
+	pub fn subscribe(
+		&self,
+		last_id: Option<u64>,
+	) -> (Replay<CardEvent>, Receiver<Published<CardEvent>>) {
+		let data = self.data.read().unwrap();
+		self.events.subscribe(last_id, data.revision)
+	}
+
+	/// The current revision together with the changes made after the revision `since`
+	pub fn changes_since(&self, since: u64) -> (u64, Replay<CardEvent>) {
+		let data = self.data.read().unwrap();
+		(data.revision, self.events.since(since, data.revision))
+	}
+
+	pub fn games_seen(
+		&self,
+		card_id: &str,
+		games: &[(String, Option<String>, u64)],
+	) -> Result<(), Error> {
+		{
+			let mut data = self.data.write().unwrap();
+			data.games_seen(card_id, games, Utc::now())?;
+			self.events
+				.publish(data.revision, CardEvent::history(card_id));
+		}
+		self.try_write_to_file();
+		Ok(())
+	}
+
+	pub fn game_removed(&self, card_id: &str, game: &Game) -> Result<(), Error> {
+		{
+			let mut data = self.data.write().unwrap();
+			data.game_removed(card_id, game, Utc::now())?;
+			self.events
+				.publish(data.revision, CardEvent::history(card_id));
+		}
+		self.try_write_to_file();
+		Ok(())
+	}
+
+	pub fn get_removed_game(&self, game_id: &str) -> Result<Vec<RemovedGame>, Error> {
+		self.data.read().unwrap().get_removed_game(game_id)
+	}
+
+	pub fn get_card_history(&self, card_id: &str) -> Result<CardHistory, Error> {
+		self.data.read().unwrap().get_card_history(card_id)
+	}
+
+	pub fn list_card_history(&self) -> HashMap<String, CardHistory> {
+		self.data.read().unwrap().list_card_history()
+	}
+
+	pub fn find_duplicates(
+		&self,
+		internal: &HashMap<String, InternalInstall>,
+	) -> Vec<DuplicateGame> {
+		self.data.read().unwrap().find_duplicates(internal)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::dto::GameSource;
+
+	fn card(uid: &str) -> MicroSDCard {
+		MicroSDCard {
+			uid: uid.to_string(),
+			libid: String::new(),
+			mount: None,
+			name: uid.to_string(),
+			position: 0,
+			hidden: false,
+			tags: Default::default(),
+			notes: None,
+			archived: None,
+		}
+	}
+
+	fn game(uid: &str) -> Game {
+		Game {
+			uid: uid.to_string(),
+			name: uid.to_string(),
+			size: 1,
+			source: GameSource::Manual,
+			tags: Default::default(),
+			notes: None,
+		}
+	}
+
+	fn group(uid: &str) -> Group {
+		Group {
+			uid: uid.to_string(),
+			name: uid.to_string(),
+			order: vec![],
+		}
+	}
+
+	/// c1 holds g1 and is in p1, c2, g2 & p2 are on their own
+	fn store() -> StoreData {
+		let mut data = StoreData::new();
+		for operation in [
+			Operation::AddGame {
+				game: game("g1"),
+				cards: vec![],
+			},
+			Operation::AddGame {
+				game: game("g2"),
+				cards: vec![],
+			},
+			Operation::AddGroup {
+				group: group("p1"),
+				cards: vec![],
+			},
+			Operation::AddGroup {
+				group: group("p2"),
+				cards: vec![],
+			},
+			Operation::AddCard {
+				card: card("c1"),
+				games: vec!["g1".to_string()],
+				groups: vec!["p1".to_string()],
+			},
+			Operation::AddCard {
+				card: card("c2"),
+				games: vec![],
+				groups: vec![],
+			},
+		] {
+			data.apply(&operation).unwrap();
+		}
+		data
+	}
+
+	fn snapshot(data: &StoreData) -> serde_json::Value {
+		let history: BTreeMap<&String, &CardHistory> = data.all_card_history().collect();
+		serde_json::json!({
+			"export": Export::from_store(data, DateTime::UNIX_EPOCH),
+			"history": history,
+		})
+	}
+
+	#[test]
+	fn applying_the_inverse_restores_the_store() {
+		let renamed = |mut card: MicroSDCard| {
+			card.name = "Renamed".to_string();
+			card
+		};
+
+		let operations = vec![
+			Operation::AddCard {
+				card: card("c3"),
+				games: vec!["g1".to_string()],
+				groups: vec!["p1".to_string()],
+			},
+			Operation::RemoveCard {
+				card: card("c2"),
+				games: vec![],
+				groups: vec![],
+			},
+			Operation::UpdateCard {
+				before: card("c1"),
+				after: renamed(card("c1")),
+			},
+			Operation::AddGame {
+				game: game("g3"),
+				cards: vec!["c1".to_string()],
+			},
+			Operation::RemoveGame {
+				game: game("g2"),
+				cards: vec![],
+			},
+			Operation::UpdateGame {
+				before: game("g1"),
+				after: Game {
+					size: 2,
+					..game("g1")
+				},
+			},
+			Operation::AddGroup {
+				group: group("p3"),
+				cards: vec!["c2".to_string()],
+			},
+			Operation::RemoveGroup {
+				group: group("p2"),
+				cards: vec![],
+			},
+			Operation::UpdateGroup {
+				before: group("p1"),
+				after: Group {
+					order: vec!["c1".to_string()],
+					..group("p1")
+				},
+			},
+			Operation::AddToGroup {
+				group_id: "p2".to_string(),
+				card_id: "c1".to_string(),
+			},
+			Operation::RemoveFromGroup {
+				group_id: "p1".to_string(),
+				card_id: "c1".to_string(),
+			},
+			Operation::Link {
+				game_id: "g2".to_string(),
+				card_id: "c1".to_string(),
+			},
+			Operation::Unlink {
+				game_id: "g1".to_string(),
+				card_id: "c1".to_string(),
+			},
+			Operation::SetHistory {
+				card_id: "c1".to_string(),
+				before: None,
+				after: Some(CardHistory::new(Utc::now())),
+			},
+		];
+
+		for operation in operations {
+			let mut data = store();
+			let before = snapshot(&data);
+
+			data.apply(&operation).unwrap();
+			assert_ne!(snapshot(&data), before, "{operation:?} changed nothing");
+
+			data.apply(&operation.inverse()).unwrap();
+			assert_eq!(snapshot(&data), before, "{operation:?} was not reverted");
+		}
+	}
+
+	#[test]
+	fn rejected_operations_leave_the_store_untouched() {
+		let operations = vec![
+			Operation::UpdateCard {
+				before: MicroSDCard {
+					hidden: true,
+					..card("c2")
+				},
+				after: card("c2"),
+			},
+			// c1 still holds g1, so the recorded links are out of date
+			Operation::RemoveCard {
+				card: card("c1"),
+				games: vec![],
+				groups: vec!["p1".to_string()],
+			},
+			Operation::AddCard {
+				card: card("c3"),
+				games: vec!["missing".to_string()],
+				groups: vec![],
+			},
+			Operation::Link {
+				game_id: "g1".to_string(),
+				card_id: "c1".to_string(),
+			},
+			Operation::SetHistory {
+				card_id: "c1".to_string(),
+				before: Some(CardHistory::new(Utc::now())),
+				after: None,
+			},
+		];
+
+		for operation in operations {
+			let mut data = store();
+			let before = snapshot(&data);
+
+			assert!(data.apply(&operation).is_err(), "{operation:?} applied");
+			assert_eq!(snapshot(&data), before, "{operation:?} changed the store");
+			assert_eq!(data.revision, store().revision);
+		}
+	}
 }
@@ -1,35 +1,115 @@
-use crate::{err::Error, event::EventTrait};
+use crate::{err::Error, event::EventTrait, patch::apply_patch};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
-pub enum CardEvent {
+/// Number of sessions retained per card
+const MAX_SESSIONS: usize = 20;
+
+/// Number of largest games listed in statistics
+pub const LARGEST_GAMES: usize = 5;
+
+/// Fields of a card users can change through a patch. The rest is managed by the backend or has its own endpoints
+const CARD_EDITABLE: [&str; 5] = ["name", "position", "hidden", "tags", "notes"];
+
+/// Fields of a game users can change through a patch, unless its source leaves them to the watcher
+const GAME_EDITABLE: [&str; 5] = ["name", "size", "source", "tags", "notes"];
+
+/// Editable fields of cards & games which are optional and can therefore be removed by a patch
+const NULLABLE: [&str; 1] = ["notes"];
+
+/// How an element changed
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+	/// The card was inserted into the device
 	Inserted,
+	/// The card was taken out of the device
 	Removed,
+	Added,
 	Updated,
+	Deleted,
+}
+
+/// A change to the store, sent to clients listening for events
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct CardEvent {
+	pub kind: ChangeKind,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub card_id: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub game_id: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub group_id: Option<String>,
+	/// Elements linked by the change. Games for a card, cards for a game or group
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub linked: Vec<String>,
+	/// Elements unlinked by the change
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub unlinked: Vec<String>,
+	/// Names of the fields which changed
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub fields: Vec<String>,
+}
+
+impl CardEvent {
+	pub fn new(kind: ChangeKind) -> Self {
+		CardEvent {
+			kind,
+			card_id: None,
+			game_id: None,
+			group_id: None,
+			linked: vec![],
+			unlinked: vec![],
+			fields: vec![],
+		}
+	}
+
+	pub fn card(kind: ChangeKind, card_id: &str) -> Self {
+		CardEvent {
+			card_id: Some(card_id.to_string()),
+			..CardEvent::new(kind)
+		}
+	}
+
+	pub fn game(kind: ChangeKind, game_id: &str) -> Self {
+		CardEvent {
+			game_id: Some(game_id.to_string()),
+			..CardEvent::new(kind)
+		}
+	}
+
+	pub fn group(kind: ChangeKind, group_id: &str) -> Self {
+		CardEvent {
+			group_id: Some(group_id.to_string()),
+			..CardEvent::new(kind)
+		}
+	}
+
+	/// The insertion history of the card changed
+	pub fn history(card_id: &str) -> Self {
+		CardEvent {
+			fields: vec!["history".to_string()],
+			..CardEvent::card(ChangeKind::Updated, card_id)
+		}
+	}
 }
 
 impl EventTrait for CardEvent {
 	fn get_event(&self) -> Option<&'static str> {
-		Some(match self {
-			CardEvent::Inserted => "insert",
-			CardEvent::Removed => "remove",
-			CardEvent::Updated => "update",
+		Some(match self.kind {
+			ChangeKind::Inserted => "insert",
+			ChangeKind::Removed => "remove",
+			ChangeKind::Added | ChangeKind::Updated | ChangeKind::Deleted => "update",
 		})
 	}
-	// fn get_data(&self) -> Option<&'static str> {
-	// 	match self {
-	// 		CardEvent::Inserted => None,
-	// 		CardEvent::Removed => None,
-	// 		CardEvent::Updated => Some("Hello, World!"),
-	// 	}
-	// }
-}
-
-fn default_true() -> bool {
-	true
+	fn get_data(&self) -> Option<String> {
+		serde_json::to_string(self).ok()
+	}
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
 pub struct MicroSDCard {
 	pub uid: String,
 	pub libid: String,
@@ -42,16 +122,51 @@ pub struct MicroSDCard {
 	pub position: u32,
 	#[serde(default)]
 	pub hidden: bool,
+
+	#[serde(default)]
+	pub tags: BTreeSet<String>,
+	#[serde(default)]
+	pub notes: Option<String>,
+
+	/// Set once the card is lost or retired. Archived cards keep their games but are hidden from listings
+	#[serde(default)]
+	pub archived: Option<Archived>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Archived {
+	pub date: DateTime<Utc>,
+	#[serde(default)]
+	pub reason: Option<String>,
+}
+
+const MERGED_INTO: &str = "Merged into ";
+
+impl Archived {
+	/// Archive entry of a card retired by merging it into another card
+	pub fn merged_into(target_id: &str, date: DateTime<Utc>) -> Self {
+		Archived {
+			date,
+			reason: Some(format!("{MERGED_INTO}{target_id}")),
+		}
+	}
+
+	/// The card this one was merged into, if it was retired by a merge
+	pub fn merge_target(&self) -> Option<&str> {
+		self.reason.as_deref()?.strip_prefix(MERGED_INTO)
+	}
 }
 
 impl MicroSDCard {
+	/// Copies the name, position & visibility of `other`. Tags & notes are left untouched since clients which predate them
+	/// send cards without them; they change through their own endpoints or a patch. Archiving has its own operations
 	pub fn merge(&mut self, other: &MicroSDCard) -> Result<(), Error> {
 		if self.uid != other.uid {
-			return Error::new_res("uid's did not match");
+			return Err(Error::invalid_input("uid's did not match"));
 		}
 
 		if self.libid != other.libid {
-			return Error::new_res("libid's did not match");
+			return Err(Error::invalid_input("libid's did not match"));
 		}
 
 		self.name = other.name.clone();
@@ -60,14 +175,483 @@ impl MicroSDCard {
 
 		Ok(())
 	}
+
+	/// Applies a JSON merge patch to the user editable fields of the card
+	pub fn patch(&mut self, patch: &serde_json::Value) -> Result<(), Error> {
+		let mut patched = apply_patch(self, patch, &CARD_EDITABLE, &NULLABLE)?;
+
+		patched.tags = patched
+			.tags
+			.iter()
+			.map(|tag| normalize_tag(tag))
+			.collect::<Result<_, _>>()?;
+		patched.notes = patched.notes.as_deref().and_then(normalize_notes);
+
+		*self = patched;
+		Ok(())
+	}
+}
+
+/// Where a game comes from. This decides which fields of the game the watcher keeps in sync with the card
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GameSource {
+	/// Installed by Steam & found by the watcher in the app manifests on the card
+	SteamManifest,
+	/// Non-Steam game added to the Steam library as a shortcut
+	SteamShortcut,
+	/// Added by hand
+	Manual,
+	/// ROM found by a scanner such as an emulator frontend
+	ScannedRom,
+	/// Installed through a launcher other than Steam, e.g Heroic or Lutris
+	OtherLauncher,
+}
+
+impl GameSource {
+	/// Games the watcher adds & unlinks again once they are gone from the card. These were reported as `is_steam` before there were sources
+	pub fn is_steam(&self) -> bool {
+		*self == GameSource::SteamManifest
+	}
+
+	/// Fields the watcher overwrites with what it reads from the card. Changing them by hand would be undone by the next scan
+	pub fn scanned_fields(&self) -> &'static [&'static str] {
+		match self {
+			GameSource::SteamManifest => &["name", "size"],
+			GameSource::SteamShortcut
+			| GameSource::Manual
+			| GameSource::ScannedRom
+			| GameSource::OtherLauncher => &[],
+		}
+	}
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(from = "GameRepr", into = "GameRepr")]
 pub struct Game {
 	pub uid: String,
 	pub name: String,
 	pub size: u64,
+	/// Games sent without a source are taken from `is_steam` instead
+	#[schema(required = false)]
+	pub source: GameSource,
+	#[schema(required = false)]
+	pub tags: BTreeSet<String>,
+	pub notes: Option<String>,
+}
+
+/// Serialized form of [`Game`]. `is_steam` is still written for v1 clients & older backends reading the store,
+/// and is used for the source of games stored or sent without one
+#[derive(Serialize, Deserialize)]
+struct GameRepr {
+	uid: String,
+	name: String,
+	size: u64,
+	#[serde(default)]
+	source: Option<GameSource>,
+	#[serde(default)]
+	is_steam: Option<bool>,
+	#[serde(default)]
+	tags: BTreeSet<String>,
+	#[serde(default)]
+	notes: Option<String>,
+}
+
+impl From<GameRepr> for Game {
+	fn from(repr: GameRepr) -> Self {
+		// Games written before `is_steam` existed were all found by the watcher
+		let source = repr.source.unwrap_or(match repr.is_steam.unwrap_or(true) {
+			true => GameSource::SteamManifest,
+			false => GameSource::Manual,
+		});
+
+		Game {
+			uid: repr.uid,
+			name: repr.name,
+			size: repr.size,
+			source,
+			tags: repr.tags,
+			notes: repr.notes,
+		}
+	}
+}
+
+impl From<Game> for GameRepr {
+	fn from(game: Game) -> Self {
+		GameRepr {
+			uid: game.uid,
+			name: game.name,
+			size: game.size,
+			is_steam: Some(game.source.is_steam()),
+			source: Some(game.source),
+			tags: game.tags,
+			notes: game.notes,
+		}
+	}
+}
+
+impl Game {
+	/// Takes over the fields the watcher owns for the source of the game from what it read from the card
+	pub fn rescanned(&mut self, scanned: &Game) {
+		let fields = self.source.scanned_fields();
+		if fields.contains(&"name") {
+			self.name = scanned.name.clone();
+		}
+		if fields.contains(&"size") {
+			self.size = scanned.size;
+		}
+	}
+
+	/// Applies a JSON merge patch to the fields of the game which are not owned by the watcher
+	pub fn patch(&mut self, patch: &serde_json::Value) -> Result<(), Error> {
+		let editable: Vec<&str> = GAME_EDITABLE
+			.into_iter()
+			.filter(|field| !self.source.scanned_fields().contains(field))
+			.collect();
+		let mut patched = apply_patch(self, patch, &editable, &NULLABLE)?;
+
+		if patched.source.is_steam() != self.source.is_steam() {
+			return Err(Error::invalid_input(
+				"Only the watcher decides which games come from Steam manifests",
+			));
+		}
+
+		patched.tags = patched
+			.tags
+			.iter()
+			.map(|tag| normalize_tag(tag))
+			.collect::<Result<_, _>>()?;
+		patched.notes = patched.notes.as_deref().and_then(normalize_notes);
+
+		*self = patched;
+		Ok(())
+	}
+}
+
+/// A named collection of cards
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Group {
+	pub uid: String,
+	pub name: String,
+	/// Uids of the cards in the order they are shown within the group. Members missing from it are shown last
+	#[serde(default)]
+	pub order: Vec<String>,
+}
+
+impl Group {
+	/// Sorts members of the group into the group's order, falling back to the card position
+	pub fn sort_cards(&self, cards: &mut [MicroSDCard]) {
+		let rank = |card: &MicroSDCard| {
+			self.order
+				.iter()
+				.position(|uid| *uid == card.uid)
+				.unwrap_or(usize::MAX)
+		};
+
+		cards.sort_by(|a, b| {
+			rank(a)
+				.cmp(&rank(b))
+				.then_with(|| a.position.cmp(&b.position))
+				.then_with(|| a.uid.cmp(&b.uid))
+		});
+	}
+}
+
+/// Trims a user supplied tag, rejecting ones that are empty
+pub fn normalize_tag(tag: &str) -> Result<String, Error> {
+	match tag.trim() {
+		"" => Err(Error::invalid_input("Tag cannot be empty")),
+		tag => Ok(tag.to_string()),
+	}
+}
+
+/// Trims user supplied notes, treating empty notes as none
+pub fn normalize_notes(notes: &str) -> Option<String> {
+	Some(notes.trim().to_string()).filter(|notes| !notes.is_empty())
+}
+
+/// How often a tag is used
+#[derive(Serialize, Debug, Clone, Default, ToSchema)]
+pub struct TagUsage {
+	pub cards: usize,
+	pub games: usize,
+}
+
+/// A single period during which a card was inserted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Session {
+	pub inserted: DateTime<Utc>,
+	#[serde(default)]
+	pub removed: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct GameSighting {
+	pub first_seen: DateTime<Utc>,
+	pub last_seen: DateTime<Utc>,
+	/// Steam build id of the install the last time it was read from the card
+	#[serde(default)]
+	pub build_id: Option<String>,
+	/// Size in bytes of the install the last time it was read from the card
+	#[serde(default)]
+	pub size: Option<u64>,
+	/// Set when the game disappeared from the card, cleared once it shows up again
+	#[serde(default)]
+	pub tombstone: Option<Tombstone>,
+}
+
+/// Left behind when a game is removed from a card
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Tombstone {
+	pub removed: DateTime<Utc>,
+	/// Steam build id of the install that was removed, if known
+	pub build_id: Option<String>,
+	/// Size in bytes of the game when it was removed
+	pub size: u64,
+}
+
+/// A game which used to be on a card
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct RemovedGame {
+	pub card_id: String,
+	/// The card, unless it has been deleted since
+	pub card: Option<MicroSDCard>,
+	pub first_seen: DateTime<Utc>,
+	pub tombstone: Tombstone,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct CardHistory {
+	pub first_seen: DateTime<Utc>,
+	pub last_seen: DateTime<Utc>,
+	pub insertions: u32,
+	/// Total seconds the card has been inserted across all finished sessions
+	pub mounted_seconds: u64,
+	/// The most recent sessions, oldest first
+	pub sessions: Vec<Session>,
+	/// When each game was first and last seen on the card
+	#[serde(default)]
+	pub games: BTreeMap<String, GameSighting>,
+}
+
+impl CardHistory {
+	pub fn new(now: DateTime<Utc>) -> Self {
+		CardHistory {
+			first_seen: now,
+			last_seen: now,
+			insertions: 0,
+			mounted_seconds: 0,
+			sessions: vec![],
+			games: BTreeMap::new(),
+		}
+	}
+
+	pub fn start_session(&mut self, now: DateTime<Utc>) {
+		// A session still open means we were never told about the removal (e.g the backend was stopped).
+		// The last time the card was seen is the best guess we have for when it ended
+		let last_seen = self.last_seen;
+		self.end_session(last_seen);
+
+		self.insertions += 1;
+		self.last_seen = now;
+		self.sessions.push(Session {
+			inserted: now,
+			removed: None,
+		});
+
+		if self.sessions.len() > MAX_SESSIONS {
+			self.sessions.drain(..self.sessions.len() - MAX_SESSIONS);
+		}
+	}
+
+	pub fn end_session(&mut self, now: DateTime<Utc>) {
+		let Some(session) = self
+			.sessions
+			.last_mut()
+			.filter(|session| session.removed.is_none())
+		else {
+			return;
+		};
+
+		let removed = now.max(session.inserted);
+		session.removed = Some(removed);
+		self.mounted_seconds += (removed - session.inserted).num_seconds() as u64;
+		self.last_seen = self.last_seen.max(removed);
+	}
+
+	/// Leaves a tombstone for a game which was removed from the card
+	pub fn remove_game(&mut self, game_id: &str, size: u64, now: DateTime<Utc>) {
+		let sighting = self
+			.games
+			.entry(game_id.to_string())
+			.or_insert(GameSighting {
+				first_seen: now,
+				last_seen: now,
+				build_id: None,
+				size: None,
+				tombstone: None,
+			});
+
+		sighting.tombstone = Some(Tombstone {
+			removed: now,
+			build_id: sighting.build_id.clone(),
+			size: sighting.size.unwrap_or(size),
+		});
+	}
+
+	/// Folds the history of another card into this one, e.g when the other card was cloned onto this one
+	pub fn absorb(&mut self, other: &CardHistory) {
+		self.first_seen = self.first_seen.min(other.first_seen);
+		self.last_seen = self.last_seen.max(other.last_seen);
+		self.insertions += other.insertions;
+		self.mounted_seconds += other.mounted_seconds;
+
+		self.sessions.extend(other.sessions.iter().cloned());
+		self.sessions.sort_by_key(|session| session.inserted);
+		if self.sessions.len() > MAX_SESSIONS {
+			self.sessions.drain(..self.sessions.len() - MAX_SESSIONS);
+		}
+
+		for (game_id, sighting) in &other.games {
+			self.games
+				.entry(game_id.clone())
+				.and_modify(|existing| {
+					existing.first_seen = existing.first_seen.min(sighting.first_seen);
+					if sighting.last_seen > existing.last_seen {
+						existing.last_seen = sighting.last_seen;
+						existing.build_id = sighting.build_id.clone().or(existing.build_id.take());
+						existing.size = sighting.size.or(existing.size);
+					}
+				})
+				.or_insert_with(|| sighting.clone());
+		}
+	}
+
+	/// Records that the game was seen on the card. A missing build id or size keeps the previously known one
+	pub fn see_game(
+		&mut self,
+		game_id: &str,
+		build_id: Option<&str>,
+		size: Option<u64>,
+		now: DateTime<Utc>,
+	) {
+		self.last_seen = self.last_seen.max(now);
+
+		let sighting = self
+			.games
+			.entry(game_id.to_string())
+			.or_insert(GameSighting {
+				first_seen: now,
+				last_seen: now,
+				build_id: None,
+				size: None,
+				tombstone: None,
+			});
+
+		sighting.last_seen = now;
+		sighting.tombstone = None;
+		if let Some(build_id) = build_id {
+			sighting.build_id = Some(build_id.to_string());
+		}
+		if size.is_some() {
+			sighting.size = size;
+		}
+	}
+}
+
+/// A copy of a game on a card
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct CardInstall {
+	pub card: MicroSDCard,
+	pub build_id: Option<String>,
+	/// Size in bytes of the install the last time it was read from the card, the size of the game if it never was
+	pub size: u64,
+	pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// A copy of a game on the internal storage of the device
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct InternalInstall {
+	pub size: u64,
+	pub build_id: Option<String>,
+}
+
+/// A game that is installed more than once
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct DuplicateGame {
+	pub game: Game,
+	pub cards: Vec<CardInstall>,
+	pub internal: Option<InternalInstall>,
+	/// Bytes freed by keeping only the largest copy
+	pub reclaimable: u64,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct CardStats {
+	pub card: MicroSDCard,
+	pub games: usize,
+	pub steam_games: usize,
+	pub non_steam_games: usize,
+	/// Total size in bytes of all games linked to the card
+	pub total_size: u64,
+	/// The largest games on the card, largest first
+	pub largest_games: Vec<Game>,
+}
+
+impl CardStats {
+	pub fn new(card: MicroSDCard, games: &[Game]) -> Self {
+		let steam_games = games.iter().filter(|game| game.source.is_steam()).count();
+
+		CardStats {
+			card,
+			games: games.len(),
+			steam_games,
+			non_steam_games: games.len() - steam_games,
+			total_size: games.iter().map(|game| game.size).sum(),
+			largest_games: largest_games(games),
+		}
+	}
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct CollectionStats {
+	/// Number of cards in use, archived cards are only counted in `archived_cards`
+	pub cards: usize,
+	pub archived_cards: usize,
+	/// Number of distinct games, regardless of how many cards they are on
+	pub unique_games: usize,
+	pub steam_games: usize,
+	pub non_steam_games: usize,
+	/// Total size in bytes of every game on every card, counting duplicates once per card
+	pub total_size: u64,
+	/// Total size in bytes of every distinct game
+	pub unique_size: u64,
+	/// The largest games in the collection, largest first
+	pub largest_games: Vec<Game>,
+	pub per_card: Vec<CardStats>,
+}
+
+/// Returns the largest games, largest first
+pub fn largest_games(games: &[Game]) -> Vec<Game> {
+	let mut games = games.to_vec();
+	games.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.uid.cmp(&b.uid)));
+	games.truncate(LARGEST_GAMES);
+	games
+}
 
-	#[serde(default = "default_true")]
-	pub is_steam: bool,
+/// What merging one card into another changes
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct MergePlan {
+	pub source: MicroSDCard,
+	/// The target card before and after the merge
+	pub before: MicroSDCard,
+	pub after: MicroSDCard,
+	/// Non-Steam games which get linked to the target
+	pub games: Vec<Game>,
+	/// Groups the target gets added to
+	pub groups: Vec<Group>,
+	/// Whether the source has any history to carry over
+	pub history: bool,
+	/// Whether the source gets archived
+	pub retire: bool,
 }
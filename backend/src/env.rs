@@ -24,6 +24,8 @@ lazy_static! {
 			TEMPDIR.to_string() + "/log"
 		}
 	});
+	/// Token required on mutating requests. Read by the Decky frontend through the plugin's python backend
+	pub static ref TOKEN_PATH: PathBuf = DATA_DIR.join("token");
 	pub static ref CONFIG_PATH: PathBuf = match std::env::var("DECKY_CONFIG_PATH") {
 		Ok(loc) => PathBuf::from(loc),
 		Err(_) => DATA_DIR.join("config.toml"),
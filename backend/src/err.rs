@@ -1,7 +1,21 @@
 #![allow(dead_code)]
 
-use actix_web::ResponseError;
-use std::fmt;
+use actix_web::{
+	body::{BoxBody, MessageBody},
+	dev::{ServiceRequest, ServiceResponse},
+	http::{
+		header::{ContentType, ETAG},
+		StatusCode,
+	},
+	middleware::Next,
+	HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt};
+use utoipa::{
+	openapi::{ContentBuilder, RefOr, Response, ResponseBuilder},
+	IntoResponses, ToSchema,
+};
 
 #[derive(Debug)]
 struct StdErr(String);
@@ -14,26 +28,119 @@ impl fmt::Display for StdErr {
 	}
 }
 
-#[derive(Debug)]
-pub struct Error(String);
+/// What went wrong, reported to clients as a stable error code
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+	/// The element or resource does not exist
+	NotFound,
+	/// The request is malformed or fails validation
+	InvalidInput,
+	/// The request changes something but does not carry a valid token
+	Unauthorized,
+	/// The request clashes with the current state, e.g the element already exists
+	Conflict,
+	/// The request was based on a revision of the element which is no longer current
+	RevisionMismatch,
+	/// The request cannot be served right now, e.g no card is inserted
+	Unavailable,
+	/// Anything else. These are bugs or failures of the device itself
+	Internal,
+}
+
+impl ErrorKind {
+	pub fn status_code(&self) -> StatusCode {
+		match self {
+			ErrorKind::NotFound => StatusCode::NOT_FOUND,
+			ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+			ErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+			ErrorKind::Conflict | ErrorKind::RevisionMismatch => StatusCode::CONFLICT,
+			ErrorKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+			ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+}
+
+/// Serialized as the JSON body of failed requests
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Error {
+	code: ErrorKind,
+	message: String,
+	/// Current revision of the element, if the request was based on an outdated one
+	#[serde(skip_serializing_if = "Option::is_none")]
+	revision: Option<u64>,
+	/// Current state of the element, if the request was based on an outdated revision of it
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[schema(value_type = Option<Object>)]
+	current: Option<serde_json::Value>,
+	/// Sent as `ETag` along with the current state
+	#[serde(skip)]
+	etag: Option<String>,
+}
 
 impl Error {
+	pub fn new(code: ErrorKind, value: &str) -> Self {
+		Error {
+			code,
+			message: value.to_string(),
+			revision: None,
+			current: None,
+			etag: None,
+		}
+	}
+
 	pub fn new_boxed(value: &str) -> Box<Error> {
-		Box::new(Error(value.to_string()))
+		Box::new(Error::from_str(value))
 	}
 
 	pub fn from_str(value: &str) -> Self {
-		Error(value.to_string())
+		Error::new(ErrorKind::Internal, value)
 	}
 
 	pub fn new_res<T>(value: &str) -> Result<T, Self> {
-		Err(Error(value.to_string()))
+		Err(Error::from_str(value))
+	}
+
+	pub fn not_found(value: &str) -> Self {
+		Error::new(ErrorKind::NotFound, value)
+	}
+
+	pub fn invalid_input(value: &str) -> Self {
+		Error::new(ErrorKind::InvalidInput, value)
+	}
+
+	pub fn unauthorized(value: &str) -> Self {
+		Error::new(ErrorKind::Unauthorized, value)
+	}
+
+	pub fn conflict(value: &str) -> Self {
+		Error::new(ErrorKind::Conflict, value)
+	}
+
+	pub fn unavailable(value: &str) -> Self {
+		Error::new(ErrorKind::Unavailable, value)
+	}
+
+	pub fn revision_mismatch(value: &str) -> Self {
+		Error::new(ErrorKind::RevisionMismatch, value)
+	}
+
+	/// Attaches the current state of the element so the client can retry without fetching it again
+	pub fn with_current(mut self, revision: u64, etag: String, current: serde_json::Value) -> Self {
+		self.revision = Some(revision);
+		self.etag = Some(etag);
+		self.current = Some(current);
+		self
+	}
+
+	pub fn kind(&self) -> ErrorKind {
+		self.code
 	}
 }
 
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{}", &self.0)
+		write!(f, "{}", &self.message)
 	}
 }
 
@@ -45,28 +152,98 @@ impl From<Error> for Box<dyn std::error::Error> {
 
 impl<T: std::error::Error + Send + Sync + 'static> From<T> for Error {
 	fn from(e: T) -> Self {
-		Error(e.to_string())
+		Error::from_str(&e.to_string())
 	}
 }
 
 impl ResponseError for Error {
-	fn status_code(&self) -> actix_web::http::StatusCode {
-		actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+	fn status_code(&self) -> StatusCode {
+		self.code.status_code()
 	}
 
-	fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
-		let res = actix_web::HttpResponse::new(self.status_code());
-		res.set_body(actix_web::body::BoxBody::new(format!("{}", self)))
+	fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+		let mut response = HttpResponse::build(self.status_code());
+		if let Some(ref etag) = self.etag {
+			response.insert_header((ETAG, etag.clone()));
+		}
+		response.json(self)
 	}
 }
 
 impl ResponseError for Box<Error> {
-	fn status_code(&self) -> actix_web::http::StatusCode {
-		actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+	fn status_code(&self) -> StatusCode {
+		self.as_ref().status_code()
+	}
+
+	fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+		self.as_ref().error_response()
 	}
+}
 
-	fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
-		let res = actix_web::HttpResponse::new(self.status_code());
-		res.set_body(actix_web::body::BoxBody::new(format!("{}", self)))
+impl IntoResponses for Error {
+	fn responses() -> BTreeMap<String, RefOr<Response>> {
+		[
+			(
+				StatusCode::BAD_REQUEST,
+				"The request is malformed or fails validation",
+			),
+			(
+				StatusCode::UNAUTHORIZED,
+				"The request changes something but carries no valid token",
+			),
+			(StatusCode::NOT_FOUND, "The element does not exist"),
+			(
+				StatusCode::CONFLICT,
+				"The request clashes with the current state, or was based on an outdated revision",
+			),
+			(StatusCode::INTERNAL_SERVER_ERROR, "The request failed"),
+			(
+				StatusCode::SERVICE_UNAVAILABLE,
+				"The request cannot be served right now",
+			),
+		]
+		.into_iter()
+		.map(|(status, description)| {
+			let response = ResponseBuilder::new()
+				.description(description)
+				.content(
+					"application/json",
+					ContentBuilder::new()
+						.schema(Some(RefOr::Ref(utoipa::openapi::Ref::from_schema_name(
+							Error::name(),
+						))))
+						.build(),
+				)
+				.build();
+			(status.as_u16().to_string(), RefOr::T(response))
+		})
+		.collect()
 	}
 }
+
+/// Answers failed requests with the bare message as text, like the API did before errors were JSON. Status codes are kept
+pub async fn plain_text_errors(
+	req: ServiceRequest,
+	next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+	let response = next.call(req).await?;
+	let Some(error) = response.response().error().and_then(as_api_error) else {
+		return Ok(response.map_into_boxed_body());
+	};
+
+	let mut plain = HttpResponse::build(response.status());
+	if let Some(ref etag) = error.etag {
+		plain.insert_header((ETAG, etag.clone()));
+	}
+	let plain = plain
+		.content_type(ContentType::plaintext())
+		.body(error.message);
+
+	Ok(response.into_response(plain))
+}
+
+fn as_api_error(err: &actix_web::Error) -> Option<Error> {
+	err.as_error::<Error>()
+		.or_else(|| err.as_error::<Box<Error>>().map(AsRef::as_ref))
+		.cloned()
+}
@@ -1,15 +1,17 @@
 use actix_web::web::Bytes;
+use std::{collections::VecDeque, sync::Mutex};
+use tokio::sync::broadcast::{self, Receiver, Sender};
 
 pub(crate) struct Event<T: EventTrait>(T);
 
 pub(crate) trait EventTrait {
-	fn get_id(&self) -> Option<&'static str> {
+	fn get_id(&self) -> Option<String> {
 		None
 	}
 	fn get_event(&self) -> Option<&'static str> {
 		None
 	}
-	fn get_data(&self) -> Option<&'static str> {
+	fn get_data(&self) -> Option<String> {
 		None
 	}
 }
@@ -56,9 +58,9 @@ impl<T: EventTrait> From<T> for Event<T> {
 }
 
 pub(crate) struct EventBuilder {
-	id: Option<&'static str>,
+	id: Option<String>,
 	event: Option<&'static str>,
-	data: Option<&'static str>,
+	data: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -70,28 +72,139 @@ impl EventBuilder {
 			data: None,
 		}
 	}
-	pub fn with_id(mut self, id: &'static str) -> Self {
-		self.id = Some(id);
+	pub fn with_id(mut self, id: impl Into<String>) -> Self {
+		self.id = Some(id.into());
 		self
 	}
 	pub fn with_event(mut self, event: &'static str) -> Self {
 		self.event = Some(event);
 		self
 	}
-	pub fn with_data(mut self, data: &'static str) -> Self {
-		self.data = Some(data);
+	pub fn with_data(mut self, data: impl Into<String>) -> Self {
+		self.data = Some(data.into());
 		self
 	}
 }
 
 impl EventTrait for EventBuilder {
-	fn get_data(&self) -> Option<&'static str> {
-		self.data
+	fn get_data(&self) -> Option<String> {
+		self.data.clone()
 	}
 	fn get_event(&self) -> Option<&'static str> {
 		self.event
 	}
-	fn get_id(&self) -> Option<&'static str> {
-		self.id
+	fn get_id(&self) -> Option<String> {
+		self.id.clone()
+	}
+}
+
+/// An event together with the id it was published under
+#[derive(Clone, Debug)]
+pub(crate) struct Published<T> {
+	pub id: u64,
+	pub event: T,
+}
+
+impl<T: EventTrait> EventTrait for Published<T> {
+	fn get_id(&self) -> Option<String> {
+		Some(self.id.to_string())
+	}
+	fn get_event(&self) -> Option<&'static str> {
+		self.event.get_event()
+	}
+	fn get_data(&self) -> Option<String> {
+		self.event.get_data()
+	}
+}
+
+/// What a subscriber has to catch up on before following the live events
+pub(crate) enum Replay<T> {
+	/// Every event published after the one the subscriber last saw, oldest first
+	Events(Vec<Published<T>>),
+	/// The events the subscriber missed are no longer retained, so it has to reload everything
+	Reset,
+}
+
+#[derive(Debug)]
+struct Ring<T> {
+	events: VecDeque<Published<T>>,
+	/// Id of the newest event which is no longer retained
+	evicted: u64,
+}
+
+impl<T: Clone> Ring<T> {
+	fn replay(&self, last_id: u64, latest: u64) -> Replay<T> {
+		// Events after one which is no longer retained may be missing, and ids beyond the latest come from somewhere else
+		if last_id < self.evicted || last_id > latest {
+			return Replay::Reset;
+		}
+
+		Replay::Events(
+			self.events
+				.iter()
+				.filter(|event| event.id > last_id)
+				.cloned()
+				.collect(),
+		)
+	}
+}
+
+/// Publishes events and retains the newest ones so reconnecting subscribers can replay what they missed.
+/// Ids are chosen by the publisher and have to increase, but may skip values
+#[derive(Debug)]
+pub(crate) struct EventLog<T> {
+	ring: Mutex<Ring<T>>,
+	capacity: usize,
+	sender: Sender<Published<T>>,
+}
+
+impl<T: Clone> EventLog<T> {
+	/// Creates a log whose events follow `start`. Anything up to `start` can no longer be replayed
+	pub fn new(capacity: usize, start: u64) -> Self {
+		let (sender, _) = broadcast::channel(capacity);
+		EventLog {
+			ring: Mutex::new(Ring {
+				events: VecDeque::with_capacity(capacity),
+				evicted: start,
+			}),
+			capacity,
+			sender,
+		}
+	}
+
+	pub fn publish(&self, id: u64, event: T) {
+		let mut ring = self.ring.lock().unwrap();
+		let published = Published { id, event };
+
+		if ring.events.len() == self.capacity {
+			if let Some(evicted) = ring.events.pop_front() {
+				ring.evicted = evicted.id;
+			}
+		}
+		ring.events.push_back(published.clone());
+
+		// Sending while holding the lock keeps subscribe from seeing an event both in the replay & live
+		_ = self.sender.send(published);
+	}
+
+	/// Subscribes to live events, together with the events published after `last_id`.
+	/// `latest` is the newest id handed out so far, which may be newer than the last event
+	pub fn subscribe(
+		&self,
+		last_id: Option<u64>,
+		latest: u64,
+	) -> (Replay<T>, Receiver<Published<T>>) {
+		let ring = self.ring.lock().unwrap();
+		let receiver = self.sender.subscribe();
+
+		match last_id {
+			None => (Replay::Events(vec![]), receiver),
+			Some(last_id) => (ring.replay(last_id, latest), receiver),
+		}
+	}
+
+	/// The events published after `last_id`
+	pub fn since(&self, last_id: u64, latest: u64) -> Replay<T> {
+		self.ring.lock().unwrap().replay(last_id, latest)
 	}
 }
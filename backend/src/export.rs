@@ -0,0 +1,574 @@
+use crate::{
+	ds::{NodeId, StoreData},
+	dto::{CardHistory, Game, Group, MicroSDCard},
+	env::PACKAGE_VERSION,
+	err::Error,
+	journal::Operation,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// Version of the export document. Bumped whenever a change cannot be read by older versions
+pub const EXPORT_FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, ToSchema)]
+pub struct Link {
+	pub card_id: String,
+	pub game_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, ToSchema)]
+pub struct Membership {
+	pub group_id: String,
+	pub card_id: String,
+}
+
+/// Portable copy of the store which refers to elements by uid only, so it can be moved between devices
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Export {
+	pub format: u32,
+	/// Version of the backend which produced the export
+	pub version: String,
+	pub exported: DateTime<Utc>,
+	#[serde(default)]
+	pub cards: Vec<MicroSDCard>,
+	#[serde(default)]
+	pub games: Vec<Game>,
+	#[serde(default)]
+	pub groups: Vec<Group>,
+	#[serde(default)]
+	pub links: Vec<Link>,
+	#[serde(default)]
+	pub memberships: Vec<Membership>,
+	#[serde(default)]
+	pub history: BTreeMap<String, CardHistory>,
+}
+
+impl Export {
+	/// Exports the whole store. Everything is sorted so that exporting the same store twice gives the same document
+	pub fn from_store(data: &StoreData, now: DateTime<Utc>) -> Self {
+		let mut cards = vec![];
+		let mut links = vec![];
+		for (card, games) in data.list_cards_with_games() {
+			links.extend(games.into_iter().map(|game| Link {
+				card_id: card.uid.clone(),
+				game_id: game.uid,
+			}));
+			cards.push(card);
+		}
+
+		let mut groups = vec![];
+		let mut memberships = vec![];
+		for (group, members) in data.list_groups() {
+			memberships.extend(members.into_iter().map(|card| Membership {
+				group_id: group.uid.clone(),
+				card_id: card.uid,
+			}));
+			groups.push(group);
+		}
+
+		let mut games = data.list_games();
+
+		cards.sort_by(|a, b| a.uid.cmp(&b.uid));
+		games.sort_by(|a, b| a.uid.cmp(&b.uid));
+		groups.sort_by(|a, b| a.uid.cmp(&b.uid));
+		links.sort();
+		memberships.sort();
+
+		Export {
+			format: EXPORT_FORMAT,
+			version: PACKAGE_VERSION.trim().to_string(),
+			exported: now,
+			cards,
+			games,
+			groups,
+			links,
+			memberships,
+			history: data.list_card_history().into_iter().collect(),
+		}
+	}
+
+	fn validate(&self) -> Result<(), Error> {
+		if self.format > EXPORT_FORMAT {
+			return Err(Error::invalid_input(&format!(
+				"Export format {} is newer than the supported format {EXPORT_FORMAT}",
+				self.format
+			)));
+		}
+
+		let cards = unique_ids(self.cards.iter().map(|card| &card.uid), "card")?;
+		let games = unique_ids(self.games.iter().map(|game| &game.uid), "game")?;
+		let groups = unique_ids(self.groups.iter().map(|group| &group.uid), "group")?;
+
+		if let Some(link) = self
+			.links
+			.iter()
+			.find(|link| !cards.contains(&link.card_id) || !games.contains(&link.game_id))
+		{
+			return Err(Error::invalid_input(&format!(
+				"Link between card \"{}\" and game \"{}\" refers to a missing element",
+				link.card_id, link.game_id
+			)));
+		}
+
+		if let Some(membership) = self.memberships.iter().find(|membership| {
+			!cards.contains(&membership.card_id) || !groups.contains(&membership.group_id)
+		}) {
+			return Err(Error::invalid_input(&format!(
+				"Membership of card \"{}\" in group \"{}\" refers to a missing element",
+				membership.card_id, membership.group_id
+			)));
+		}
+
+		if let Some(card_id) = self.history.keys().find(|card_id| !cards.contains(card_id)) {
+			return Err(Error::invalid_input(&format!(
+				"History of card \"{card_id}\" refers to a missing element"
+			)));
+		}
+
+		Ok(())
+	}
+}
+
+fn unique_ids<'a>(
+	ids: impl Iterator<Item = &'a String>,
+	kind: &str,
+) -> Result<HashSet<&'a String>, Error> {
+	let mut unique = HashSet::new();
+
+	for id in ids {
+		if !unique.insert(id) {
+			return Err(Error::invalid_input(&format!(
+				"Export contains {kind} \"{id}\" more than once"
+			)));
+		}
+	}
+
+	Ok(unique)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+	/// The store ends up identical to the import
+	Replace,
+	/// New elements and links are added, elements present on both sides keep their local values
+	MergePreferLocal,
+	/// New elements and links are added, elements present on both sides take the imported values
+	MergePreferIncoming,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum Resolution {
+	Local,
+	Incoming,
+}
+
+/// An element which exists locally as well as in the import with different values
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Conflict {
+	pub id: NodeId,
+	pub resolution: Resolution,
+}
+
+#[derive(Serialize, Debug, Clone, Default, ToSchema)]
+pub struct ImportReport {
+	pub added: Vec<NodeId>,
+	pub updated: Vec<NodeId>,
+	pub removed: Vec<NodeId>,
+	pub conflicts: Vec<Conflict>,
+	pub linked: usize,
+	pub unlinked: usize,
+	/// Cards whose history is taken from the import
+	pub history: Vec<String>,
+	/// Cards whose history is dropped because the import has none for them
+	pub history_removed: Vec<String>,
+	pub dry_run: bool,
+}
+
+/// The operations needed to import a document, together with a report of what they change
+#[derive(Debug)]
+pub struct ImportPlan {
+	pub report: ImportReport,
+	pub operations: Vec<Operation>,
+}
+
+/// Elements only present in the import, present on both sides with different values, and only present locally
+struct Diff<T> {
+	added: Vec<T>,
+	changed: Vec<(T, T)>,
+	local_only: Vec<T>,
+}
+
+fn diff<T: Clone + PartialEq>(local: Vec<T>, incoming: &[T], uid: fn(&T) -> &String) -> Diff<T> {
+	let incoming_ids: HashSet<&String> = incoming.iter().map(uid).collect();
+	let mut local_by_id: HashMap<String, T> = HashMap::new();
+	let mut local_only = vec![];
+
+	for element in local {
+		match incoming_ids.contains(uid(&element)) {
+			true => {
+				local_by_id.insert(uid(&element).clone(), element);
+			}
+			false => local_only.push(element),
+		}
+	}
+
+	let mut added = vec![];
+	let mut changed = vec![];
+	for element in incoming {
+		match local_by_id.remove(uid(element)) {
+			None => added.push(element.clone()),
+			Some(local) if local != *element => changed.push((local, element.clone())),
+			Some(_) => {}
+		}
+	}
+
+	Diff {
+		added,
+		changed,
+		local_only,
+	}
+}
+
+/// Works out how to import the document into the store according to the strategy without modifying anything
+pub fn plan_import(
+	data: &StoreData,
+	incoming: &Export,
+	strategy: ImportStrategy,
+) -> Result<ImportPlan, Error> {
+	incoming.validate()?;
+
+	let replace = strategy == ImportStrategy::Replace;
+	let resolution = match strategy {
+		ImportStrategy::MergePreferLocal => Resolution::Local,
+		ImportStrategy::Replace | ImportStrategy::MergePreferIncoming => Resolution::Incoming,
+	};
+
+	let local = Export::from_store(data, incoming.exported);
+	let local_links: BTreeSet<Link> = local.links.into_iter().collect();
+	let local_memberships: BTreeSet<Membership> = local.memberships.into_iter().collect();
+	let incoming_links: BTreeSet<Link> = incoming.links.iter().cloned().collect();
+	let incoming_memberships: BTreeSet<Membership> = incoming.memberships.iter().cloned().collect();
+
+	let cards = diff(local.cards, &incoming.cards, |card| &card.uid);
+	let games = diff(local.games, &incoming.games, |game| &game.uid);
+	let groups = diff(local.groups, &incoming.groups, |group| &group.uid);
+
+	let mut report = ImportReport::default();
+	let mut operations = vec![];
+
+	// Links go first so that removed elements are no longer linked to anything by the time they are removed
+	if replace {
+		for link in local_links.difference(&incoming_links) {
+			report.unlinked += 1;
+			operations.push(Operation::Unlink {
+				game_id: link.game_id.clone(),
+				card_id: link.card_id.clone(),
+			});
+		}
+		for membership in local_memberships.difference(&incoming_memberships) {
+			report.unlinked += 1;
+			operations.push(Operation::RemoveFromGroup {
+				group_id: membership.group_id.clone(),
+				card_id: membership.card_id.clone(),
+			});
+		}
+
+		for card in cards.local_only {
+			report.removed.push(NodeId::Card(card.uid.clone()));
+			operations.push(Operation::RemoveCard {
+				card,
+				games: vec![],
+				groups: vec![],
+			});
+		}
+		for game in games.local_only {
+			report.removed.push(NodeId::Game(game.uid.clone()));
+			operations.push(Operation::RemoveGame {
+				game,
+				cards: vec![],
+			});
+		}
+		for group in groups.local_only {
+			report.removed.push(NodeId::Group(group.uid.clone()));
+			operations.push(Operation::RemoveGroup {
+				group,
+				cards: vec![],
+			});
+		}
+	}
+
+	for (before, after) in cards.changed {
+		let id = NodeId::Card(before.uid.clone());
+		report.conflicts.push(Conflict {
+			id: id.clone(),
+			resolution,
+		});
+		if resolution == Resolution::Incoming {
+			report.updated.push(id);
+			operations.push(Operation::UpdateCard { before, after });
+		}
+	}
+	for (before, after) in games.changed {
+		let id = NodeId::Game(before.uid.clone());
+		report.conflicts.push(Conflict {
+			id: id.clone(),
+			resolution,
+		});
+		if resolution == Resolution::Incoming {
+			report.updated.push(id);
+			operations.push(Operation::UpdateGame { before, after });
+		}
+	}
+	for (before, after) in groups.changed {
+		let id = NodeId::Group(before.uid.clone());
+		report.conflicts.push(Conflict {
+			id: id.clone(),
+			resolution,
+		});
+		if resolution == Resolution::Incoming {
+			report.updated.push(id);
+			operations.push(Operation::UpdateGroup { before, after });
+		}
+	}
+
+	for card in cards.added {
+		report.added.push(NodeId::Card(card.uid.clone()));
+		operations.push(Operation::AddCard {
+			card,
+			games: vec![],
+			groups: vec![],
+		});
+	}
+	for game in games.added {
+		report.added.push(NodeId::Game(game.uid.clone()));
+		operations.push(Operation::AddGame {
+			game,
+			cards: vec![],
+		});
+	}
+	for group in groups.added {
+		report.added.push(NodeId::Group(group.uid.clone()));
+		operations.push(Operation::AddGroup {
+			group,
+			cards: vec![],
+		});
+	}
+
+	for link in incoming_links.difference(&local_links) {
+		report.linked += 1;
+		operations.push(Operation::Link {
+			game_id: link.game_id.clone(),
+			card_id: link.card_id.clone(),
+		});
+	}
+	for membership in incoming_memberships.difference(&local_memberships) {
+		report.linked += 1;
+		operations.push(Operation::AddToGroup {
+			group_id: membership.group_id.clone(),
+			card_id: membership.card_id.clone(),
+		});
+	}
+
+	// History goes last so that it is only set once the cards it belongs to exist
+	for (card_id, history) in &incoming.history {
+		let before = data.card_history(card_id);
+		let take = match before {
+			None => true,
+			Some(existing) => resolution == Resolution::Incoming && existing != history,
+		};
+		if take {
+			report.history.push(card_id.clone());
+			operations.push(Operation::SetHistory {
+				card_id: card_id.clone(),
+				before: before.cloned(),
+				after: Some(history.clone()),
+			});
+		}
+	}
+
+	// This includes history left behind by cards which were deleted earlier, which is not part of any export
+	if replace {
+		let mut stale: Vec<(&String, &CardHistory)> = data
+			.all_card_history()
+			.filter(|(card_id, _)| !incoming.history.contains_key(*card_id))
+			.collect();
+		stale.sort_by(|a, b| a.0.cmp(b.0));
+
+		for (card_id, history) in stale {
+			report.history_removed.push(card_id.clone());
+			operations.push(Operation::SetHistory {
+				card_id: card_id.clone(),
+				before: Some(history.clone()),
+				after: None,
+			});
+		}
+	}
+
+	Ok(ImportPlan { report, operations })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::dto::GameSource;
+
+	fn card(uid: &str, name: &str) -> MicroSDCard {
+		MicroSDCard {
+			uid: uid.to_string(),
+			libid: String::new(),
+			mount: None,
+			name: name.to_string(),
+			position: 0,
+			hidden: false,
+			tags: Default::default(),
+			notes: None,
+			archived: None,
+		}
+	}
+
+	fn history() -> CardHistory {
+		CardHistory::new(DateTime::UNIX_EPOCH)
+	}
+
+	/// c1 holds g1 and has history, c2 only exists locally and "gone" left history behind when it was deleted
+	fn local() -> StoreData {
+		let mut data = StoreData::new();
+		for operation in [
+			Operation::AddGame {
+				game: Game {
+					uid: "g1".to_string(),
+					name: "Game".to_string(),
+					size: 1,
+					source: GameSource::Manual,
+					tags: Default::default(),
+					notes: None,
+				},
+				cards: vec![],
+			},
+			Operation::AddCard {
+				card: card("c1", "Local"),
+				games: vec!["g1".to_string()],
+				groups: vec![],
+			},
+			Operation::AddCard {
+				card: card("c2", "Local only"),
+				games: vec![],
+				groups: vec![],
+			},
+			Operation::AddCard {
+				card: card("gone", "Deleted"),
+				games: vec![],
+				groups: vec![],
+			},
+			Operation::SetHistory {
+				card_id: "c1".to_string(),
+				before: None,
+				after: Some(history()),
+			},
+			Operation::SetHistory {
+				card_id: "gone".to_string(),
+				before: None,
+				after: Some(history()),
+			},
+			Operation::RemoveCard {
+				card: card("gone", "Deleted"),
+				games: vec![],
+				groups: vec![],
+			},
+		] {
+			data.apply(&operation).unwrap();
+		}
+		data
+	}
+
+	/// c1 changed its name and no longer holds g1, c3 is new & holds g1 instead
+	fn incoming() -> Export {
+		let mut export = Export::from_store(&local(), DateTime::UNIX_EPOCH);
+		export.cards = vec![card("c1", "Incoming"), card("c3", "New")];
+		export.links = vec![Link {
+			card_id: "c3".to_string(),
+			game_id: "g1".to_string(),
+		}];
+		export.history = BTreeMap::from([("c3".to_string(), history())]);
+		export
+	}
+
+	fn card_ids(ids: &[NodeId]) -> Vec<&str> {
+		ids.iter()
+			.filter_map(|id| match id {
+				NodeId::Card(uid) => Some(uid.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn replace_makes_the_store_identical_to_the_import() {
+		let mut data = local();
+		let incoming = incoming();
+
+		let plan = plan_import(&data, &incoming, ImportStrategy::Replace).unwrap();
+		assert_eq!(card_ids(&plan.report.added), vec!["c3"]);
+		assert_eq!(card_ids(&plan.report.updated), vec!["c1"]);
+		assert_eq!(card_ids(&plan.report.removed), vec!["c2"]);
+		assert_eq!((plan.report.linked, plan.report.unlinked), (1, 1));
+		assert_eq!(plan.report.history, vec!["c3"]);
+		assert_eq!(plan.report.history_removed, vec!["c1", "gone"]);
+
+		for operation in &plan.operations {
+			data.apply(operation).unwrap();
+		}
+
+		let result = Export::from_store(&data, incoming.exported);
+		assert_eq!(
+			serde_json::to_value(&result).unwrap(),
+			serde_json::to_value(&incoming).unwrap()
+		);
+		assert_eq!(data.all_card_history().count(), 1);
+	}
+
+	#[test]
+	fn merging_keeps_local_only_elements_and_resolves_conflicts_by_strategy() {
+		let data = local();
+		let incoming = incoming();
+
+		for (strategy, resolution, updated) in [
+			(ImportStrategy::MergePreferLocal, Resolution::Local, vec![]),
+			(
+				ImportStrategy::MergePreferIncoming,
+				Resolution::Incoming,
+				vec!["c1"],
+			),
+		] {
+			let plan = plan_import(&data, &incoming, strategy).unwrap();
+
+			assert_eq!(card_ids(&plan.report.added), vec!["c3"]);
+			assert_eq!(card_ids(&plan.report.updated), updated);
+			assert!(plan.report.removed.is_empty());
+			assert_eq!((plan.report.linked, plan.report.unlinked), (1, 0));
+			assert_eq!(plan.report.conflicts.len(), 1);
+			assert_eq!(plan.report.conflicts[0].resolution, resolution);
+			assert_eq!(plan.report.history, vec!["c3"]);
+			assert!(plan.report.history_removed.is_empty());
+
+			let mut merged = data.clone();
+			for operation in &plan.operations {
+				merged.apply(operation).unwrap();
+			}
+			assert!(merged.get_card("c2").is_ok());
+			assert!(merged.is_linked("g1", "c1").unwrap());
+		}
+	}
+
+	#[test]
+	fn history_of_cards_missing_from_the_import_is_rejected() {
+		let mut incoming = incoming();
+		incoming.history.insert("c2".to_string(), history());
+
+		assert!(plan_import(&local(), &incoming, ImportStrategy::Replace).is_err());
+	}
+}
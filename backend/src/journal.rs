@@ -0,0 +1,533 @@
+use crate::dto::{CardEvent, CardHistory, ChangeKind, Game, Group, MicroSDCard};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::VecDeque,
+	fs::{read_to_string, OpenOptions},
+	io::Write,
+	path::PathBuf,
+};
+use tracing::{debug, error};
+use utoipa::ToSchema;
+
+/// Maximum number of entries kept in memory. Older entries are only retained on disk
+const JOURNAL_CAPACITY: usize = 1000;
+
+/// Where a mutation of the store originated from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum Origin {
+	Watcher,
+	Api,
+}
+
+/// A single reversible mutation of the store
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub enum Operation {
+	AddCard {
+		card: MicroSDCard,
+		#[serde(default)]
+		games: Vec<String>,
+		#[serde(default)]
+		groups: Vec<String>,
+	},
+	RemoveCard {
+		card: MicroSDCard,
+		#[serde(default)]
+		games: Vec<String>,
+		#[serde(default)]
+		groups: Vec<String>,
+	},
+	UpdateCard {
+		before: MicroSDCard,
+		after: MicroSDCard,
+	},
+	AddGame {
+		game: Game,
+		#[serde(default)]
+		cards: Vec<String>,
+	},
+	RemoveGame {
+		game: Game,
+		#[serde(default)]
+		cards: Vec<String>,
+	},
+	UpdateGame {
+		before: Game,
+		after: Game,
+	},
+	AddGroup {
+		group: Group,
+		#[serde(default)]
+		cards: Vec<String>,
+	},
+	RemoveGroup {
+		group: Group,
+		#[serde(default)]
+		cards: Vec<String>,
+	},
+	UpdateGroup {
+		before: Group,
+		after: Group,
+	},
+	AddToGroup {
+		group_id: String,
+		card_id: String,
+	},
+	RemoveFromGroup {
+		group_id: String,
+		card_id: String,
+	},
+	Link {
+		game_id: String,
+		card_id: String,
+	},
+	Unlink {
+		game_id: String,
+		card_id: String,
+	},
+	/// Replaces the history of a card, `None` meaning the card has no history
+	SetHistory {
+		card_id: String,
+		before: Option<CardHistory>,
+		after: Option<CardHistory>,
+	},
+}
+
+impl Operation {
+	/// The operation which reverts this one
+	pub fn inverse(&self) -> Operation {
+		match self.clone() {
+			Self::AddCard {
+				card,
+				games,
+				groups,
+			} => Self::RemoveCard {
+				card,
+				games,
+				groups,
+			},
+			Self::RemoveCard {
+				card,
+				games,
+				groups,
+			} => Self::AddCard {
+				card,
+				games,
+				groups,
+			},
+			Self::UpdateCard { before, after } => Self::UpdateCard {
+				before: after,
+				after: before,
+			},
+			Self::AddGame { game, cards } => Self::RemoveGame { game, cards },
+			Self::RemoveGame { game, cards } => Self::AddGame { game, cards },
+			Self::UpdateGame { before, after } => Self::UpdateGame {
+				before: after,
+				after: before,
+			},
+			Self::AddGroup { group, cards } => Self::RemoveGroup { group, cards },
+			Self::RemoveGroup { group, cards } => Self::AddGroup { group, cards },
+			Self::UpdateGroup { before, after } => Self::UpdateGroup {
+				before: after,
+				after: before,
+			},
+			Self::AddToGroup { group_id, card_id } => Self::RemoveFromGroup { group_id, card_id },
+			Self::RemoveFromGroup { group_id, card_id } => Self::AddToGroup { group_id, card_id },
+			Self::Link { game_id, card_id } => Self::Unlink { game_id, card_id },
+			Self::Unlink { game_id, card_id } => Self::Link { game_id, card_id },
+			Self::SetHistory {
+				card_id,
+				before,
+				after,
+			} => Self::SetHistory {
+				card_id,
+				before: after,
+				after: before,
+			},
+		}
+	}
+
+	/// The event telling clients what this operation changed
+	pub fn event(&self) -> CardEvent {
+		match self {
+			Self::AddCard { card, games, .. } => CardEvent {
+				linked: games.clone(),
+				..CardEvent::card(ChangeKind::Added, &card.uid)
+			},
+			Self::RemoveCard { card, games, .. } => CardEvent {
+				unlinked: games.clone(),
+				..CardEvent::card(ChangeKind::Deleted, &card.uid)
+			},
+			Self::UpdateCard { before, after } => CardEvent {
+				fields: changed_fields(before, after),
+				..CardEvent::card(ChangeKind::Updated, &after.uid)
+			},
+			Self::AddGame { game, cards } => CardEvent {
+				linked: cards.clone(),
+				..CardEvent::game(ChangeKind::Added, &game.uid)
+			},
+			Self::RemoveGame { game, cards } => CardEvent {
+				unlinked: cards.clone(),
+				..CardEvent::game(ChangeKind::Deleted, &game.uid)
+			},
+			Self::UpdateGame { before, after } => CardEvent {
+				fields: changed_fields(before, after),
+				..CardEvent::game(ChangeKind::Updated, &after.uid)
+			},
+			Self::AddGroup { group, cards } => CardEvent {
+				linked: cards.clone(),
+				..CardEvent::group(ChangeKind::Added, &group.uid)
+			},
+			Self::RemoveGroup { group, cards } => CardEvent {
+				unlinked: cards.clone(),
+				..CardEvent::group(ChangeKind::Deleted, &group.uid)
+			},
+			Self::UpdateGroup { before, after } => CardEvent {
+				fields: changed_fields(before, after),
+				..CardEvent::group(ChangeKind::Updated, &after.uid)
+			},
+			Self::AddToGroup { group_id, card_id } => CardEvent {
+				linked: vec![card_id.clone()],
+				..CardEvent::group(ChangeKind::Updated, group_id)
+			},
+			Self::RemoveFromGroup { group_id, card_id } => CardEvent {
+				unlinked: vec![card_id.clone()],
+				..CardEvent::group(ChangeKind::Updated, group_id)
+			},
+			Self::Link { game_id, card_id } => CardEvent {
+				linked: vec![game_id.clone()],
+				..CardEvent::card(ChangeKind::Updated, card_id)
+			},
+			Self::Unlink { game_id, card_id } => CardEvent {
+				unlinked: vec![game_id.clone()],
+				..CardEvent::card(ChangeKind::Updated, card_id)
+			},
+			Self::SetHistory { card_id, .. } => CardEvent::history(card_id),
+		}
+	}
+}
+
+/// Names of the serialized fields whose values differ between the two versions of an element
+fn changed_fields<T: Serialize>(before: &T, after: &T) -> Vec<String> {
+	let (Ok(serde_json::Value::Object(before)), Ok(serde_json::Value::Object(after))) =
+		(serde_json::to_value(before), serde_json::to_value(after))
+	else {
+		return vec![];
+	};
+
+	let mut fields: Vec<String> = after
+		.iter()
+		.filter(|(name, value)| before.get(*name) != Some(*value))
+		.map(|(name, _)| name.clone())
+		.chain(
+			before
+				.keys()
+				.filter(|name| !after.contains_key(*name))
+				.cloned(),
+		)
+		.collect();
+	fields.sort();
+	fields
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct JournalEntry {
+	pub id: u64,
+	pub timestamp: DateTime<Utc>,
+	pub origin: Origin,
+	pub operation: Operation,
+	/// Id of the entry this one reverted, if it was recorded by an undo
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub undoes: Option<u64>,
+	/// Id of the first entry recorded by the same change, if it consisted of several operations. These are undone together
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub group: Option<u64>,
+}
+
+impl JournalEntry {
+	/// Id identifying the change this entry is part of
+	pub fn unit(&self) -> u64 {
+		self.group.unwrap_or(self.id)
+	}
+}
+
+/// Append-only record of every mutation made to the store
+#[derive(Debug)]
+pub struct Journal {
+	entries: VecDeque<JournalEntry>,
+	next_id: u64,
+	file: Option<PathBuf>,
+}
+
+impl Journal {
+	pub fn new(file: Option<PathBuf>) -> Self {
+		Journal {
+			entries: VecDeque::new(),
+			next_id: 1,
+			file,
+		}
+	}
+
+	/// Loads the most recent entries from the journal file, starting a new journal if it cannot be read
+	pub fn read_from_file(file: PathBuf) -> Self {
+		let mut journal = Journal::new(Some(file.clone()));
+
+		let Ok(contents) = read_to_string(&file) else {
+			return journal;
+		};
+
+		for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+			match serde_json::from_str::<JournalEntry>(line) {
+				Ok(entry) => journal.push(entry),
+				Err(err) => error!(%err, "Unable to deserialize journal entry"),
+			}
+		}
+
+		debug!(
+			entries = journal.entries.len(),
+			next_id = journal.next_id,
+			"Loaded journal"
+		);
+
+		journal
+	}
+
+	fn push(&mut self, entry: JournalEntry) {
+		self.next_id = self.next_id.max(entry.id + 1);
+		self.entries.push_back(entry);
+
+		while self.entries.len() > JOURNAL_CAPACITY {
+			self.entries.pop_front();
+		}
+	}
+
+	/// Records the operations of a single change, each with the id of the entry it reverts if it is recorded by an undo
+	pub fn record(
+		&mut self,
+		origin: Origin,
+		operations: Vec<(Operation, Option<u64>)>,
+	) -> Vec<JournalEntry> {
+		let group = (operations.len() > 1).then_some(self.next_id);
+
+		operations
+			.into_iter()
+			.map(|(operation, undoes)| {
+				let entry = JournalEntry {
+					id: self.next_id,
+					timestamp: Utc::now(),
+					origin,
+					operation,
+					undoes,
+					group,
+				};
+
+				if let Err(err) = self.append_to_file(&entry) {
+					error!(%err, "Unable to append entry to journal file");
+				}
+
+				self.push(entry.clone());
+				entry
+			})
+			.collect()
+	}
+
+	fn append_to_file(&self, entry: &JournalEntry) -> std::io::Result<()> {
+		let Some(ref file) = self.file else {
+			return Ok(());
+		};
+
+		let mut file = OpenOptions::new().create(true).append(true).open(file)?;
+		writeln!(file, "{}", serde_json::to_string(entry)?)
+	}
+
+	pub fn get(&self, id: u64) -> Option<&JournalEntry> {
+		self.entries.iter().find(|entry| entry.id == id)
+	}
+
+	pub fn is_undone(&self, id: u64) -> bool {
+		self.entries.iter().any(|entry| entry.undoes == Some(id))
+	}
+
+	/// Returns up to `limit` entries, newest first
+	pub fn recent(&self, limit: usize) -> Vec<JournalEntry> {
+		self.entries.iter().rev().take(limit).cloned().collect()
+	}
+
+	/// The entries of the change `id` is part of which have not been undone yet, oldest first
+	pub fn unit(&self, id: u64) -> Vec<JournalEntry> {
+		let Some(unit) = self.get(id).map(JournalEntry::unit) else {
+			return vec![];
+		};
+
+		self.entries
+			.iter()
+			.filter(|entry| entry.unit() == unit && !self.is_undone(entry.id))
+			.cloned()
+			.collect()
+	}
+
+	/// Returns an entry id for each of the newest `count` changes which have not been undone, skipping changes recorded by an undo
+	pub fn undoable(&self, count: usize) -> Vec<u64> {
+		let mut units: Vec<u64> = vec![];
+
+		for entry in self
+			.entries
+			.iter()
+			.rev()
+			.filter(|entry| entry.undoes.is_none() && !self.is_undone(entry.id))
+		{
+			if units.len() == count {
+				break;
+			}
+			if !units.contains(&entry.unit()) {
+				units.push(entry.unit());
+			}
+		}
+
+		units
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::dto::GameSource;
+
+	fn card(uid: &str, name: &str) -> MicroSDCard {
+		MicroSDCard {
+			uid: uid.to_string(),
+			libid: String::new(),
+			mount: None,
+			name: name.to_string(),
+			position: 0,
+			hidden: false,
+			tags: Default::default(),
+			notes: None,
+			archived: None,
+		}
+	}
+
+	fn game(uid: &str, size: u64) -> Game {
+		Game {
+			uid: uid.to_string(),
+			name: uid.to_string(),
+			size,
+			source: GameSource::Manual,
+			tags: Default::default(),
+			notes: None,
+		}
+	}
+
+	fn group(uid: &str, name: &str) -> Group {
+		Group {
+			uid: uid.to_string(),
+			name: name.to_string(),
+			order: vec![],
+		}
+	}
+
+	fn every_operation() -> Vec<Operation> {
+		let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+		let card_id = "c1".to_string();
+
+		vec![
+			Operation::AddCard {
+				card: card("c1", "Card"),
+				games: ids(&["g1"]),
+				groups: ids(&["p1"]),
+			},
+			Operation::RemoveCard {
+				card: card("c1", "Card"),
+				games: ids(&["g1"]),
+				groups: vec![],
+			},
+			Operation::UpdateCard {
+				before: card("c1", "Card"),
+				after: card("c1", "Renamed"),
+			},
+			Operation::AddGame {
+				game: game("g1", 1),
+				cards: ids(&["c1"]),
+			},
+			Operation::RemoveGame {
+				game: game("g1", 1),
+				cards: vec![],
+			},
+			Operation::UpdateGame {
+				before: game("g1", 1),
+				after: game("g1", 2),
+			},
+			Operation::AddGroup {
+				group: group("p1", "Group"),
+				cards: ids(&["c1"]),
+			},
+			Operation::RemoveGroup {
+				group: group("p1", "Group"),
+				cards: vec![],
+			},
+			Operation::UpdateGroup {
+				before: group("p1", "Group"),
+				after: group("p1", "Renamed"),
+			},
+			Operation::AddToGroup {
+				group_id: "p1".to_string(),
+				card_id: card_id.clone(),
+			},
+			Operation::RemoveFromGroup {
+				group_id: "p1".to_string(),
+				card_id: card_id.clone(),
+			},
+			Operation::Link {
+				game_id: "g1".to_string(),
+				card_id: card_id.clone(),
+			},
+			Operation::Unlink {
+				game_id: "g1".to_string(),
+				card_id: card_id.clone(),
+			},
+			Operation::SetHistory {
+				card_id,
+				before: None,
+				after: Some(CardHistory::new(Utc::now())),
+			},
+		]
+	}
+
+	fn json(operation: &Operation) -> serde_json::Value {
+		serde_json::to_value(operation).unwrap()
+	}
+
+	#[test]
+	fn inverse_of_the_inverse_is_the_operation() {
+		for operation in every_operation() {
+			assert_ne!(json(&operation.inverse()), json(&operation));
+			assert_eq!(json(&operation.inverse().inverse()), json(&operation));
+		}
+	}
+
+	#[test]
+	fn operations_recorded_together_are_undone_together() {
+		let mut journal = Journal::new(None);
+		let operations: Vec<(Operation, Option<u64>)> = every_operation()
+			.into_iter()
+			.take(3)
+			.map(|operation| (operation, None))
+			.collect();
+
+		let entries = journal.record(Origin::Api, operations);
+		let unit = entries[0].unit();
+		assert!(entries.iter().all(|entry| entry.unit() == unit));
+		assert_eq!(journal.undoable(10), vec![unit]);
+		assert_eq!(journal.unit(entries[2].id).len(), 3);
+
+		let undo: Vec<(Operation, Option<u64>)> = entries
+			.iter()
+			.rev()
+			.map(|entry| (entry.operation.inverse(), Some(entry.id)))
+			.collect();
+		journal.record(Origin::Api, undo);
+
+		assert!(journal.undoable(10).is_empty());
+		assert!(journal.unit(unit).is_empty());
+	}
+}
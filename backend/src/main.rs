@@ -1,29 +1,37 @@
 mod api;
+mod auth;
+mod cache;
 mod cfg;
 mod ds;
 mod dto;
 mod env;
 mod err;
 mod event;
+mod export;
+mod journal;
 mod log;
+mod patch;
+mod query;
 mod sdcard;
+mod search;
 mod steam;
 mod watch;
+mod ws;
+use crate::api::config;
+use crate::auth::{require_token, Token};
 use crate::cfg::CONFIG;
 use crate::ds::Store;
 use crate::env::*;
 use crate::watch::start_watch;
-use crate::{api::config, dto::CardEvent};
 use actix_cors::Cors;
-use actix_web::{web, App, HttpServer};
+use actix_web::{middleware::from_fn, web, App, HttpResponse, HttpServer};
 use err::Error;
 use futures::{pin_mut, select, FutureExt};
 use log::create_subscriber;
 use std::path::PathBuf;
 use std::process::exit;
 use std::sync::Arc;
-use tokio::sync::broadcast::{self, Sender};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 pub async fn init() {
 	create_subscriber().await;
@@ -31,25 +39,60 @@ pub async fn init() {
 
 type MainResult = Result<(), Error>;
 
-async fn run_web_server(port: u16, datastore: Arc<Store>, sender: Sender<CardEvent>) -> MainResult {
-	info!("Starting HTTP server on port {port}...");
+/// Maximum size of a JSON request body in bytes
+const IMPORT_LIMIT: usize = 16 * 1024 * 1024;
+
+async fn run_web_server(
+	address: String,
+	port: u16,
+	allowed_origins: Vec<String>,
+	token: Token,
+	datastore: Arc<Store>,
+) -> MainResult {
+	info!("Starting HTTP server on {address}:{port}...");
 
 	HttpServer::new(move || {
+		let allowed_origins = allowed_origins.clone();
 		let cors = Cors::default()
 			.allow_any_header()
 			.allow_any_method()
-			.allow_any_origin()
+			.allowed_origin_fn(move |origin, _| {
+				allowed_origins
+					.iter()
+					.any(|allowed| allowed == "*" || origin.as_bytes() == allowed.as_bytes())
+			})
+			.expose_headers(["X-Next-Cursor", "Deprecation", "Link", "ETag"])
 			.max_age(3600);
 
 		App::new()
+			// Runs inside of CORS so preflight requests are answered without a token
+			.wrap(from_fn(require_token))
 			.wrap(cors)
+			.app_data(web::Data::new(token.clone()))
 			// .app_data(web::Data::new(api::AppState{datastore: datastore.clone()}))
 			.app_data(web::Data::new(datastore.clone()))
-			.app_data(web::Data::new(sender.clone()))
+			// Imports carry the whole store which easily exceeds the default limit
+			.app_data(
+				web::JsonConfig::default()
+					.limit(IMPORT_LIMIT)
+					.error_handler(|err, _| Error::invalid_input(&err.to_string()).into()),
+			)
+			// Malformed requests are answered with the same JSON body as every other error
+			.app_data(
+				web::QueryConfig::default()
+					.error_handler(|err, _| Error::invalid_input(&err.to_string()).into()),
+			)
+			.app_data(
+				web::PathConfig::default()
+					.error_handler(|err, _| Error::invalid_input(&err.to_string()).into()),
+			)
 			.configure(config)
+			.default_service(web::to(|| async {
+				Err::<HttpResponse, _>(Error::not_found("No such route"))
+			}))
 	})
 	.workers(2)
-	.bind(("0.0.0.0", port))?
+	.bind((address.as_str(), port))?
 	.run()
 	.await
 	.map_err(|err| err.into())
@@ -68,16 +111,26 @@ async fn main() {
 		"{}@{} by {}", PACKAGE_NAME, PACKAGE_VERSION, PACKAGE_AUTHORS
 	);
 
-	let (store_file, skip_clean, skip_validate, port) = {
+	let (store_file, skip_clean, skip_validate, address, port, allowed_origins) = {
 		let config = CONFIG.read().await;
 		(
 			config.backend.store_file.clone(),
 			config.backend.startup.skip_clean,
 			config.backend.startup.skip_validate,
+			config.backend.bind_address.clone(),
 			config.backend.port,
+			config.backend.allowed_origins.clone(),
 		)
 	};
 
+	let token = match Token::load_or_create(&TOKEN_PATH) {
+		Ok(token) => token,
+		Err(err) => {
+			error!(%err, "Unable to load or create the API token. Cannot run backend...");
+			exit(1);
+		}
+	};
+
 	let store_path = PathBuf::from(
 		&std::env::var("STORE_PATH").map(PathBuf::from).unwrap_or(
 			get_file_path_and_create_directory(&store_file, &DATA_DIR)
@@ -85,27 +138,47 @@ async fn main() {
 		),
 	);
 
-	debug!(store_path = store_path.to_str(), "Loading from store");
-	let store: Arc<Store> =
-		Arc::new(Store::read_from_file(store_path.clone()).unwrap_or(Store::new(Some(store_path))));
+	// Starting with an empty store would overwrite a store that merely failed to load, so only a missing file does that
+	let store: Arc<Store> = match store_path.exists() {
+		false => {
+			info!(
+				store_path = store_path.to_str(),
+				"No store found, starting with an empty one"
+			);
+			Arc::new(Store::new(Some(store_path)))
+		}
+		true => {
+			debug!(store_path = store_path.to_str(), "Loading from store");
+			match Store::read_from_file(store_path.clone()) {
+				Ok(store) => Arc::new(store),
+				Err(err) => {
+					error!(%err, store_path = store_path.to_str(), "Unable to load the store. Cannot run backend...");
+					exit(1);
+				}
+			}
+		}
+	};
 
 	if !skip_clean {
 		store.clean_up();
 	}
 
 	if !skip_validate && !store.validate() {
-		error!("Validity of the data is not guaranteed. Cannot run backend...");
-		exit(1);
+		warn!("Store failed validation. Attempting to repair...");
+		store.repair();
+
+		if !store.validate() {
+			error!("Validity of the data is not guaranteed. Cannot run backend...");
+			exit(1);
+		}
 	}
 
 	info!("Database Started...");
 	info!("Starting Program...");
 
-	let (txtx, _) = broadcast::channel::<CardEvent>(1);
-
-	let server_future = run_web_server(port, store.clone(), txtx.clone()).fuse();
+	let server_future = run_web_server(address, port, allowed_origins, token, store.clone()).fuse();
 
-	let watch_future = start_watch(store.clone(), txtx.clone()).fuse();
+	let watch_future = start_watch(store.clone()).fuse();
 
 	pin_mut!(server_future, watch_future);
 
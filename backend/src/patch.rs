@@ -0,0 +1,126 @@
+use crate::err::Error;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// Merges `patch` into `target` as described by JSON merge patch (RFC 7386): objects are merged recursively,
+/// `null` removes a member and anything else replaces it
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+	let Value::Object(patch) = patch else {
+		*target = patch.clone();
+		return;
+	};
+
+	if !target.is_object() {
+		*target = Value::Object(Default::default());
+	}
+	let Value::Object(target) = target else {
+		return;
+	};
+
+	for (name, value) in patch {
+		match value {
+			Value::Null => {
+				target.remove(name);
+			}
+			value => merge_patch(target.entry(name.clone()).or_insert(Value::Null), value),
+		}
+	}
+}
+
+/// Applies a merge patch to an element. Only the `editable` fields may change, every other field has to either be left out or keep its value.
+/// Only `nullable` fields may be removed with `null`, removing any other field would silently reset it to its default
+pub fn apply_patch<T: Serialize + DeserializeOwned>(
+	element: &T,
+	patch: &Value,
+	editable: &[&str],
+	nullable: &[&str],
+) -> Result<T, Error> {
+	let Value::Object(fields) = patch else {
+		return Err(Error::invalid_input("Patch has to be a JSON object"));
+	};
+
+	let mut value = serde_json::to_value(element)?;
+
+	for (name, patched) in fields {
+		if editable.contains(&name.as_str()) {
+			if patched.is_null() && !nullable.contains(&name.as_str()) {
+				return Err(Error::invalid_input(&format!(
+					"Field \"{name}\" cannot be null"
+				)));
+			}
+			continue;
+		}
+
+		match value.get(name) {
+			None => return Err(Error::invalid_input(&format!("Unknown field \"{name}\""))),
+			Some(current) if current != patched => {
+				return Err(Error::invalid_input(&format!(
+					"Field \"{name}\" cannot be changed"
+				)))
+			}
+			Some(_) => {}
+		}
+	}
+
+	merge_patch(&mut value, patch);
+	serde_json::from_value(value).map_err(|err| Error::invalid_input(&err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::Deserialize;
+	use serde_json::json;
+
+	#[derive(Serialize, Deserialize, Debug, PartialEq)]
+	struct Element {
+		uid: String,
+		#[serde(default)]
+		position: u32,
+		#[serde(default)]
+		notes: Option<String>,
+	}
+
+	fn element() -> Element {
+		Element {
+			uid: "e1".to_string(),
+			position: 3,
+			notes: Some("Notes".to_string()),
+		}
+	}
+
+	fn patch(patch: serde_json::Value) -> Result<Element, Error> {
+		apply_patch(&element(), &patch, &["position", "notes"], &["notes"])
+	}
+
+	#[test]
+	fn editable_fields_change() {
+		assert_eq!(
+			patch(json!({"position": 5, "notes": null})).unwrap(),
+			Element {
+				position: 5,
+				notes: None,
+				..element()
+			}
+		);
+	}
+
+	#[test]
+	fn other_fields_may_only_be_repeated() {
+		assert_eq!(patch(json!({"uid": "e1"})).unwrap(), element());
+		assert!(patch(json!({"uid": "e2"})).is_err());
+		assert!(patch(json!({"unknown": 1})).is_err());
+	}
+
+	#[test]
+	fn null_only_removes_optional_fields() {
+		assert!(patch(json!({"position": null})).is_err());
+		assert!(patch(json!({"uid": null})).is_err());
+	}
+
+	#[test]
+	fn values_have_to_match_the_field_type() {
+		assert!(patch(json!({"position": "first"})).is_err());
+		assert!(patch(json!([])).is_err());
+	}
+}
@@ -0,0 +1,273 @@
+use crate::{dto::GameSource, err::Error, search::fold};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::BTreeSet};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+	Name,
+	Size,
+	Position,
+	LastSeen,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+	#[default]
+	Asc,
+	Desc,
+}
+
+/// Filters, sorting and pagination for listings. Filters that do not apply to the listed element are ignored
+#[derive(Deserialize, Debug, Clone, Default, IntoParams)]
+pub struct ListQuery {
+	/// Only the given card, or only games linked to the given card
+	pub card: Option<String>,
+	/// Only Steam or only non-Steam games
+	pub is_steam: Option<bool>,
+	/// Only games from the given source
+	pub source: Option<GameSource>,
+	/// Only hidden or only visible cards
+	pub hidden: Option<bool>,
+	/// List archived cards instead of active ones
+	#[serde(default)]
+	pub archived: bool,
+	/// Minimum size in bytes of a game, or the total size of the games on a card
+	pub min_size: Option<u64>,
+	/// Maximum size in bytes of a game, or the total size of the games on a card
+	pub max_size: Option<u64>,
+	/// Case & accent insensitive prefix of the name
+	pub prefix: Option<String>,
+	/// Only cards or games carrying the tag. In combined listings this applies to the cards
+	pub tag: Option<String>,
+	#[param(inline)]
+	pub sort: Option<SortKey>,
+	#[serde(default)]
+	#[param(inline)]
+	pub order: SortOrder,
+	/// Opaque cursor returned by the previous page
+	pub cursor: Option<String>,
+	pub limit: Option<usize>,
+}
+
+impl ListQuery {
+	pub fn matches_size(&self, size: u64) -> bool {
+		self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+	}
+
+	pub fn matches_tags(&self, tags: &BTreeSet<String>) -> bool {
+		self.tag
+			.as_ref()
+			.is_none_or(|tag| tags.contains(tag.trim()))
+	}
+
+	pub fn matches_name(&self, name: &str) -> bool {
+		self.prefix
+			.as_ref()
+			.is_none_or(|prefix| fold(name).starts_with(&fold(prefix)))
+	}
+}
+
+/// The values an element can be sorted by
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SortValues {
+	pub name: String,
+	pub size: u64,
+	pub position: u32,
+	pub last_seen: Option<DateTime<Utc>>,
+}
+
+impl SortValues {
+	fn compare(&self, other: &SortValues, key: SortKey) -> Ordering {
+		match key {
+			SortKey::Name => fold(&self.name).cmp(&fold(&other.name)),
+			SortKey::Size => self.size.cmp(&other.size),
+			SortKey::Position => self.position.cmp(&other.position),
+			SortKey::LastSeen => self.last_seen.cmp(&other.last_seen),
+		}
+	}
+}
+
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+	pub items: Vec<T>,
+	/// Cursor for the following page, if there is one
+	pub next: Option<String>,
+}
+
+/// Position of the last element of a page. The following page starts after it, even if the element itself is gone by then
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+	values: SortValues,
+	uid: String,
+}
+
+// Cursors are hex encoded JSON so that arbitrary non-Steam ids can be passed around in headers and query strings
+fn encode_cursor(values: &SortValues, uid: &str) -> String {
+	let cursor = Cursor {
+		values: values.clone(),
+		uid: uid.to_string(),
+	};
+
+	serde_json::to_vec(&cursor)
+		.unwrap_or_default()
+		.iter()
+		.map(|b| format!("{b:02x}"))
+		.collect()
+}
+
+fn decode_cursor(cursor: &str) -> Result<Cursor, Error> {
+	let invalid = || Error::invalid_input("Invalid cursor");
+
+	if !cursor.is_ascii() || !cursor.len().is_multiple_of(2) {
+		return Err(invalid());
+	}
+
+	let bytes = (0..cursor.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).map_err(|_| invalid()))
+		.collect::<Result<Vec<u8>, Error>>()?;
+
+	serde_json::from_slice(&bytes).map_err(|_| invalid())
+}
+
+/// Sorts the items by the requested key (falling back to `default`) with the uid as tiebreaker, then cuts out the requested page
+pub fn paginate<T>(
+	mut items: Vec<(SortValues, T)>,
+	uid: impl Fn(&T) -> &str,
+	query: &ListQuery,
+	default: SortKey,
+) -> Result<Page<T>, Error> {
+	let key = query.sort.unwrap_or(default);
+
+	let compare = |a_values: &SortValues, a_uid: &str, b_values: &SortValues, b_uid: &str| {
+		let ordering = a_values
+			.compare(b_values, key)
+			.then_with(|| a_uid.cmp(b_uid));
+
+		match query.order {
+			SortOrder::Asc => ordering,
+			SortOrder::Desc => ordering.reverse(),
+		}
+	};
+
+	items.sort_by(|(a_values, a), (b_values, b)| compare(a_values, uid(a), b_values, uid(b)));
+
+	let start = match query.cursor {
+		None => 0,
+		Some(ref cursor) => {
+			let after = decode_cursor(cursor)?;
+			items.partition_point(|(values, item)| {
+				compare(values, uid(item), &after.values, &after.uid) != Ordering::Greater
+			})
+		}
+	};
+
+	let limit = query.limit.unwrap_or(usize::MAX).max(1);
+	let mut items: Vec<(SortValues, T)> = items.into_iter().skip(start).collect();
+
+	let next = match items.len() > limit {
+		true => {
+			let (values, item) = &items[limit - 1];
+			Some(encode_cursor(values, uid(item)))
+		}
+		false => None,
+	};
+
+	items.truncate(limit);
+	Ok(Page {
+		items: items.into_iter().map(|(_, item)| item).collect(),
+		next,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn items(sizes: &[u64]) -> Vec<(SortValues, String)> {
+		sizes
+			.iter()
+			.enumerate()
+			.map(|(i, size)| {
+				let values = SortValues {
+					name: String::new(),
+					size: *size,
+					position: 0,
+					last_seen: None,
+				};
+				(values, format!("e{i}"))
+			})
+			.collect()
+	}
+
+	fn page(items: Vec<(SortValues, String)>, query: &ListQuery) -> Page<String> {
+		paginate(items, |uid| uid.as_str(), query, SortKey::Size).unwrap()
+	}
+
+	#[test]
+	fn pages_cover_every_item_once() {
+		// Equal sizes are ordered by uid
+		let sizes = [5, 1, 5, 3, 5, 2];
+		let mut query = ListQuery {
+			limit: Some(2),
+			..Default::default()
+		};
+
+		let mut seen = vec![];
+		loop {
+			let page = page(items(&sizes), &query);
+			assert!(page.items.len() <= 2);
+			seen.extend(page.items);
+			match page.next {
+				Some(next) => query.cursor = Some(next),
+				None => break,
+			}
+		}
+
+		assert_eq!(seen, vec!["e1", "e5", "e3", "e0", "e2", "e4"]);
+	}
+
+	#[test]
+	fn last_full_page_has_no_cursor() {
+		let query = ListQuery {
+			limit: Some(3),
+			..Default::default()
+		};
+		assert!(page(items(&[1, 2, 3]), &query).next.is_none());
+		assert!(page(items(&[1, 2, 3, 4]), &query).next.is_some());
+	}
+
+	#[test]
+	fn cursor_survives_removal_of_its_item() {
+		let query = ListQuery {
+			limit: Some(2),
+			..Default::default()
+		};
+		let first = page(items(&[1, 2, 3, 4]), &query);
+		assert_eq!(first.items, vec!["e0", "e1"]);
+
+		// e1 is gone by the time the next page is requested
+		let mut remaining = items(&[1, 2, 3, 4]);
+		remaining.remove(1);
+		let query = ListQuery {
+			cursor: first.next,
+			..query
+		};
+		assert_eq!(page(remaining, &query).items, vec!["e2", "e3"]);
+	}
+
+	#[test]
+	fn invalid_cursors_are_rejected() {
+		for cursor in ["zz", "abc", "7b7d"] {
+			let query = ListQuery {
+				cursor: Some(cursor.to_string()),
+				..Default::default()
+			};
+			assert!(paginate(items(&[1]), |uid| uid.as_str(), &query, SortKey::Size).is_err());
+		}
+	}
+}
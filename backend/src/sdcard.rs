@@ -5,8 +5,10 @@ use std::{
 
 pub const DEFAULT_MOUNT: &str = "mmcblk0p1";
 pub const LIBRARY_FOLDER_FILE: &str = "libraryfolder.vdf";
+pub const INTERNAL_STEAMAPPS: &str = "/home/deck/.local/share/Steam/steamapps/";
 
-use crate::err::Error;
+use crate::{err::Error, steam::AppState};
+use tracing::error;
 
 pub fn is_card_inserted() -> bool {
 	std::fs::metadata("/sys/block/mmcblk0").is_ok()
@@ -46,3 +48,29 @@ pub fn get_steam_acf_files(
 	.filter_map(Result::ok)
 	.filter(|f| f.path().extension().unwrap_or_default().eq("acf")))
 }
+
+pub fn get_internal_acf_files() -> Result<impl Iterator<Item = DirEntry>, Error> {
+	Ok(fs::read_dir(INTERNAL_STEAMAPPS)?
+		.filter_map(Result::ok)
+		.filter(|f| f.path().extension().unwrap_or_default().eq("acf")))
+}
+
+/// Reads & deserializes Steam ACF files, skipping any that cannot be read
+pub fn read_acf_files(files: impl Iterator<Item = DirEntry>) -> Vec<AppState> {
+	files
+		.filter_map(|f| match fs::read_to_string(f.path()) {
+			Ok(value) => Some(value),
+			Err(err) => {
+				error!(%err, path=?f.path(), "Unable to read Steam ACF file {:?}", f.path());
+				None
+			}
+		})
+		.filter_map(|s| match keyvalues_serde::from_str(s.as_str()) {
+			Ok(value) => Some(value),
+			Err(err) => {
+				error!(%err, contents=s.as_str(), "Unable to Deserialize Steam ACF file");
+				None
+			}
+		})
+		.collect()
+}
@@ -0,0 +1,227 @@
+use crate::{
+	ds::NodeId,
+	dto::{Game, MicroSDCard},
+	journal::Operation,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use utoipa::ToSchema;
+
+const EXACT_SCORE: u32 = 1000;
+const PREFIX_SCORE: u32 = 800;
+const WORD_PREFIX_SCORE: u32 = 600;
+const SUBSTRING_SCORE: u32 = 400;
+const SUBSEQUENCE_SCORE: u32 = 200;
+const TYPO_SCORE: u32 = 100;
+/// Each typo costs 10 points of `TYPO_SCORE`
+const MAX_TYPOS: usize = (TYPO_SCORE / 10) as usize - 1;
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub enum SearchHit {
+	Card {
+		score: u32,
+		card: MicroSDCard,
+	},
+	Game {
+		score: u32,
+		game: Game,
+		cards: Vec<MicroSDCard>,
+	},
+}
+
+impl SearchHit {
+	pub fn score(&self) -> u32 {
+		match self {
+			Self::Card { score, .. } | Self::Game { score, .. } => *score,
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Card { card, .. } => &card.name,
+			Self::Game { game, .. } => &game.name,
+		}
+	}
+}
+
+/// Lowercases and strips diacritics so that "Pokémon" and "POKEMON" compare equal
+pub fn fold(value: &str) -> String {
+	value
+		.nfd()
+		.filter(|c| !is_combining_mark(*c))
+		.flat_map(char::to_lowercase)
+		.collect()
+}
+
+/// Scores how well a folded query matches a folded piece of text. Higher is better, `None` means no match
+pub fn score(query: &str, text: &str) -> Option<u32> {
+	if query.is_empty() {
+		return None;
+	}
+
+	let whole = score_term(query, text);
+
+	// Each word of a multi word query may also match on its own, as long as every one of them matches somewhere
+	let terms: Vec<&str> = query.split_whitespace().collect();
+	if terms.len() < 2 {
+		return whole;
+	}
+
+	let per_term = terms
+		.iter()
+		.map(|term| score_term(term, text))
+		.sum::<Option<u32>>()
+		.map(|total| total / terms.len() as u32);
+
+	whole.max(per_term)
+}
+
+fn score_term(term: &str, text: &str) -> Option<u32> {
+	if text == term {
+		return Some(EXACT_SCORE);
+	}
+	if text.starts_with(term) {
+		return Some(PREFIX_SCORE);
+	}
+	if text.split_whitespace().any(|word| word.starts_with(term)) {
+		return Some(WORD_PREFIX_SCORE);
+	}
+	if text.contains(term) {
+		return Some(SUBSTRING_SCORE);
+	}
+	if let Some(gaps) = subsequence_gaps(term, text) {
+		return Some(
+			SUBSEQUENCE_SCORE
+				.saturating_sub(gaps * 5)
+				.max(TYPO_SCORE + 1),
+		);
+	}
+
+	// Allow roughly one typo for every four characters, but never so many that a typo match would score nothing
+	let allowed = (term.chars().count() / 4).clamp(1, MAX_TYPOS);
+	text.split_whitespace()
+		.filter_map(|word| {
+			let distance = levenshtein(term, word);
+			(distance <= allowed).then(|| TYPO_SCORE - distance as u32 * 10)
+		})
+		.max()
+}
+
+/// Returns the number of skipped characters if every character of `term` appears in `text` in order
+fn subsequence_gaps(term: &str, text: &str) -> Option<u32> {
+	let mut text = text.chars();
+	let mut gaps = 0;
+
+	for c in term.chars() {
+		loop {
+			match text.next() {
+				Some(t) if t == c => break,
+				Some(_) => gaps += 1,
+				None => return None,
+			}
+		}
+	}
+
+	Some(gaps)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+	for (i, ca) in a.chars().enumerate() {
+		let mut current = vec![i + 1; b.len() + 1];
+		for (j, cb) in b.iter().enumerate() {
+			let substitution = previous[j] + usize::from(ca != *cb);
+			current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+		}
+		previous = current;
+	}
+
+	previous[b.len()]
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+	id: String,
+	name: String,
+}
+
+/// Folded names and ids of every card and game, kept in sync with the store so searching never has to normalize the whole collection
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+	entries: HashMap<NodeId, IndexEntry>,
+}
+
+impl SearchIndex {
+	pub fn insert(&mut self, id: NodeId, name: &str) {
+		let entry = IndexEntry {
+			id: fold(id.as_str()),
+			name: fold(name),
+		};
+		self.entries.insert(id, entry);
+	}
+
+	pub fn remove(&mut self, id: &NodeId) {
+		self.entries.remove(id);
+	}
+
+	pub fn clear(&mut self) {
+		self.entries.clear();
+	}
+
+	/// Keeps the index in sync with an operation that was applied to the store
+	pub fn update(&mut self, operation: &Operation) {
+		match operation {
+			Operation::AddCard { card, .. } | Operation::UpdateCard { after: card, .. } => {
+				self.insert(NodeId::Card(card.uid.clone()), &card.name)
+			}
+			Operation::RemoveCard { card, .. } => self.remove(&NodeId::Card(card.uid.clone())),
+			Operation::AddGame { game, .. } | Operation::UpdateGame { after: game, .. } => {
+				self.insert(NodeId::Game(game.uid.clone()), &game.name)
+			}
+			Operation::RemoveGame { game, .. } => self.remove(&NodeId::Game(game.uid.clone())),
+			// Groups are not searchable
+			Operation::AddGroup { .. }
+			| Operation::RemoveGroup { .. }
+			| Operation::UpdateGroup { .. }
+			| Operation::AddToGroup { .. }
+			| Operation::RemoveFromGroup { .. }
+			| Operation::Link { .. }
+			| Operation::Unlink { .. } => {}
+			// Neither is history
+			Operation::SetHistory { .. } => {}
+		}
+	}
+
+	/// Returns the id and score of every element matching the query
+	pub fn search(&self, query: &str) -> Vec<(&NodeId, u32)> {
+		let query = fold(query.trim());
+
+		self.entries
+			.iter()
+			.filter_map(|(id, entry)| {
+				let score = score(&query, &entry.name).max(score(&query, &entry.id))?;
+				Some((id, score))
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn long_query_with_typos_scores_above_zero() {
+		let text = "a".repeat(48);
+
+		let query = format!("{}{}", "a".repeat(40), "b".repeat(8));
+		assert_eq!(score_term(&query, &text), Some(TYPO_SCORE - 80));
+
+		// 11 typos used to be allowed for a term this long, scoring below zero
+		let query = format!("{}{}", "a".repeat(37), "b".repeat(11));
+		assert_eq!(score_term(&query, &text), None);
+	}
+}
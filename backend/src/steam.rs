@@ -20,6 +20,8 @@ pub struct AppState {
 	pub state_flags: Option<i32>,
 	pub installdir: String,
 	pub size_on_disk: u64,
+	#[serde(default)]
+	pub buildid: Option<String>,
 }
 
 impl Display for AppState {
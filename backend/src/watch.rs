@@ -1,9 +1,15 @@
 use crate::cfg::CONFIG;
-use crate::{ds::Store, dto::*, err::Error, sdcard::*, steam::*};
+use crate::{
+	ds::{NodeId, Store},
+	dto::*,
+	err::Error,
+	journal::Origin,
+	sdcard::*,
+	steam::*,
+};
 use std::borrow::Borrow;
 use std::path::Path;
-use std::{fs, sync::Arc, time::Duration};
-use tokio::sync::broadcast::Sender;
+use std::{sync::Arc, time::Duration};
 use tokio::time::interval;
 use tracing::{debug, error, info, span, trace, warn};
 
@@ -17,22 +23,7 @@ fn read_microsd_steam_dir(datastore: &Store, mount: &Option<String>) -> Result<(
 		"Read & deserialized library from {}", LIBRARY_FOLDER_FILE
 	);
 
-	let games: Vec<AppState> = get_steam_acf_files(mount)?
-		.filter_map(|f| match fs::read_to_string(f.path()) {
-			Ok(value) => Some(value),
-			Err(err) => {
-				error!(%err, path=?f.path(), "Unable to read Steam ACF file {:?}", f.path());
-				None
-			}
-		})
-		.filter_map(|s| match keyvalues_serde::from_str(s.as_str()) {
-			Ok(value) => Some(value),
-			Err(err) => {
-				error!(%err, contents=s.as_str(), "Unable to Deserialize Steam ACF file");
-				None
-			}
-		})
-		.collect();
+	let games: Vec<AppState> = read_acf_files(get_steam_acf_files(mount)?);
 
 	debug!(
 		game_count = games.len(),
@@ -41,11 +32,11 @@ fn read_microsd_steam_dir(datastore: &Store, mount: &Option<String>) -> Result<(
 		games.len()
 	);
 
-	if !datastore.contains_element(&cid) {
+	if !datastore.contains_element(&NodeId::Card(cid.clone())) {
 		debug!(cid, "No MicroSD card found, creating new card");
 
 		datastore.add_card(
-			cid.clone(),
+			Origin::Watcher,
 			MicroSDCard {
 				uid: cid.clone(),
 				libid: library.contentid.clone(),
@@ -53,8 +44,14 @@ fn read_microsd_steam_dir(datastore: &Store, mount: &Option<String>) -> Result<(
 				name: library.label,
 				position: u32::MAX,
 				hidden: false,
+				tags: Default::default(),
+				notes: None,
+				archived: None,
 			},
-		);
+		)?;
+
+		// The insertion could not be recorded before the card existed
+		datastore.card_inserted(&cid)?;
 	}
 
 	// Remove any games that are linked to the card in the database but on the card
@@ -66,28 +63,59 @@ fn read_microsd_steam_dir(datastore: &Store, mount: &Option<String>) -> Result<(
 	);
 	for deleted_game in current_games
 		.iter()
-		.filter(|v| v.is_steam && !games.iter().any(|g| g.appid == v.uid))
+		.filter(|v| v.source.is_steam() && !games.iter().any(|g| g.appid == v.uid))
 	{
 		debug!(game = ?deleted_game, cid, "Game was removed from MicroSD card");
-		datastore.unlink(&deleted_game.uid, &cid)?
+		datastore.unlink(Origin::Watcher, &deleted_game.uid, &cid)?;
+		datastore.game_removed(&cid, deleted_game)?;
+	}
+
+	let scanned: Vec<Game> = games
+		.iter()
+		.map(|game| Game {
+			uid: game.appid.clone(),
+			name: game.name.clone(),
+			size: game.size_on_disk,
+			source: GameSource::SteamManifest,
+			tags: Default::default(),
+			notes: None,
+		})
+		.collect();
+
+	debug!(cid, "Adding, updating & linking {} games", scanned.len());
+	datastore.games_scanned(Origin::Watcher, &cid, &scanned)?;
+
+	let seen: Vec<(String, Option<String>, u64)> = games
+		.iter()
+		.map(|game| (game.appid.clone(), game.buildid.clone(), game.size_on_disk))
+		.collect();
+	datastore.games_seen(&cid, &seen)?;
+
+	Ok(())
+}
+
+/// Cards which never made it into the store, e.g because their library could not be read, have no history to record it in
+fn record_removal(datastore: &Store, cid: &str) {
+	if let Err(err) = datastore.card_removed(cid) {
+		debug!(%err, cid, "Removal of MicroSD card was not recorded");
 	}
+}
 
-	for game in games.iter() {
-		if !datastore.contains_element(&game.appid) {
-			debug!(?game, "Game not found in database. Adding game");
-			datastore.add_game(
-				game.appid.clone(),
-				Game {
-					uid: game.appid.clone(),
-					name: game.name.clone(),
-					size: game.size_on_disk,
-					is_steam: true,
-				},
+/// An inserted card clearly is not lost anymore, unless it was retired on purpose by merging it into another card
+fn restore_inserted_card(datastore: &Store, cid: &str) -> Result<(), Error> {
+	match datastore.get_card(cid)?.archived {
+		Some(archived) if archived.merge_target().is_some() => {
+			info!(
+				cid,
+				target = archived.merge_target(),
+				"MicroSD card which was merged into another card was inserted, keeping it archived"
 			);
 		}
-
-		debug!(?game, cid, "Linking game to MicroSD card");
-		datastore.link(&game.appid, &cid).expect("game to be added")
+		Some(_) => {
+			info!(cid, "Archived MicroSD card was inserted, restoring card");
+			datastore.restore_card(Origin::Watcher, cid)?;
+		}
+		None => {}
 	}
 
 	Ok(())
@@ -136,7 +164,7 @@ fn find_mount_name() -> Result<Option<String>, Error> {
 	Ok(None)
 }
 
-pub async fn start_watch(datastore: Arc<Store>, sender: Sender<CardEvent>) -> Result<(), Error> {
+pub async fn start_watch(datastore: Arc<Store>) -> Result<(), Error> {
 	let scan_interval = {
 		let config = CONFIG.read().await;
 		config.backend.scan_interval
@@ -146,6 +174,9 @@ pub async fn start_watch(datastore: Arc<Store>, sender: Sender<CardEvent>) -> Re
 
 	let mut card_inserted = false;
 
+	// The card whose insertion is currently being tracked in its history
+	let mut current_card: Option<String> = None;
+
 	info!("Starting Watcher...");
 
 	// Small cache for optimization purposes
@@ -161,8 +192,10 @@ pub async fn start_watch(datastore: Arc<Store>, sender: Sender<CardEvent>) -> Re
 			// The card has been removed since the last check
 			if card_inserted {
 				debug!("Card was removed");
-				trace!("Sending Removed event");
-				let _ = sender.send(CardEvent::Removed);
+			}
+			if let Some(cid) = current_card.take() {
+				trace!(card_id = cid, "Recording removal in card history");
+				record_removal(datastore.borrow(), &cid);
 			}
 			card_inserted = false;
 			mount = None;
@@ -172,8 +205,6 @@ pub async fn start_watch(datastore: Arc<Store>, sender: Sender<CardEvent>) -> Re
 
 		if !card_inserted {
 			debug!("Card was inserted");
-			trace!("Sending Inserted event");
-			let _ = sender.send(CardEvent::Inserted);
 			mount = None;
 		}
 
@@ -190,6 +221,26 @@ pub async fn start_watch(datastore: Arc<Store>, sender: Sender<CardEvent>) -> Re
 			}
 		};
 
+		// Cards can be swapped between two cycles so the id has to be compared as well
+		if current_card.as_ref() != Some(&cid) {
+			if let Some(previous) = current_card.replace(cid.clone()) {
+				trace!(card_id = previous, "Recording removal in card history");
+				record_removal(datastore.borrow(), &previous);
+			}
+			// Cards inserted for the first time are recorded once reading their library added them
+			if datastore.contains_element(&NodeId::Card(cid.clone())) {
+				trace!(card_id = cid, "Recording insertion in card history");
+				if let Err(err) = datastore.card_inserted(&cid) {
+					error!(%err, cid, "Failed to record insertion of MicroSD card");
+				}
+
+				// This has to happen on insertion, the library of a card which is reinserted unchanged is not read again
+				if let Err(err) = restore_inserted_card(datastore.borrow(), &cid) {
+					error!(%err, cid, "Failed to restore archived MicroSD card");
+				}
+			}
+		}
+
 		// If we have a mount point and it does not resolve to the library folder, we need to determine the mount point
 		if !has_libraryfolder(&mount) {
 			debug!(
@@ -228,7 +279,7 @@ pub async fn start_watch(datastore: Arc<Store>, sender: Sender<CardEvent>) -> Re
 			}
 
 			debug!(mount = mount, "Updating card's mount point");
-			let _ = datastore.update_card(&cid, |card| {
+			let _ = datastore.update_card(Origin::Watcher, &cid, None, |card| {
 				card.mount = mount.clone();
 				Ok(())
 			});
@@ -261,8 +312,5 @@ pub async fn start_watch(datastore: Arc<Store>, sender: Sender<CardEvent>) -> Re
 		// commit update
 		trace!(hash, "Updating hash in database");
 		datastore.update_hash(&cid, hash);
-
-		trace!("Sending Updated event");
-		let _ = sender.send(CardEvent::Updated);
 	}
 }
@@ -0,0 +1,241 @@
+use crate::{
+	auth::{bearer_token, Token},
+	ds::Store,
+	dto::{CardEvent, MicroSDCard},
+	err::Error,
+	event::{Published, Replay},
+	journal::Origin,
+	sdcard::get_card_cid,
+};
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use actix_ws::{Message, MessageStream, Session};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{debug, instrument, trace, warn};
+use utoipa::IntoParams;
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct SessionQuery {
+	/// Token for clients which cannot set the `Authorization` header, like browsers
+	token: Option<String>,
+	/// Id of the last event received. Events published since are replayed, like `Last-Event-ID` on `/listen`
+	last_event_id: Option<u64>,
+}
+
+impl fmt::Debug for SessionQuery {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("SessionQuery")
+			.field("token", &self.token.as_ref().map(|_| "<redacted>"))
+			.field("last_event_id", &self.last_event_id)
+			.finish()
+	}
+}
+
+/// Commands sent by clients as JSON text messages. An `id` sent along with the command is echoed in its result
+#[derive(Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+	Link {
+		card_id: String,
+		game_id: String,
+	},
+	Unlink {
+		card_id: String,
+		game_id: String,
+	},
+	/// Merges the card into the stored one, like `POST /card/{id}` does for an existing card
+	UpdateCard {
+		card: MicroSDCard,
+	},
+	/// Makes the watcher reread the library of the card, the inserted one if none is given
+	Rescan {
+		#[serde(default)]
+		card_id: Option<String>,
+	},
+}
+
+/// Messages sent to clients
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+	/// The same events `/listen` sends
+	Event { id: u64, event: CardEvent },
+	/// The events missed are no longer retained, so the client has to reload everything
+	Reset,
+	/// Outcome of a command. The command succeeded if there is no error
+	Result {
+		id: Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		error: Option<Error>,
+	},
+}
+
+impl From<Published<CardEvent>> for Reply {
+	fn from(value: Published<CardEvent>) -> Self {
+		Reply::Event {
+			id: value.id,
+			event: value.event,
+		}
+	}
+}
+
+fn execute(datastore: &Store, command: Command) -> Result<(), Error> {
+	match command {
+		Command::Link { card_id, game_id } => datastore.link(Origin::Api, &game_id, &card_id),
+		Command::Unlink { card_id, game_id } => datastore.unlink(Origin::Api, &game_id, &card_id),
+		Command::UpdateCard { card } => {
+			datastore.update_card(Origin::Api, &card.uid, None, |existing| {
+				existing.merge(&card)
+			})
+		}
+		Command::Rescan { card_id } => {
+			let card_id = match card_id {
+				Some(card_id) => card_id,
+				None => get_card_cid().ok_or_else(|| Error::unavailable("No card is inserted"))?,
+			};
+			datastore.forget_hash(&card_id)
+		}
+	}
+}
+
+fn handle(datastore: &Store, text: &str, authorized: bool) -> Reply {
+	let value: serde_json::Value = match serde_json::from_str(text) {
+		Ok(value) => value,
+		Err(err) => {
+			return Reply::Result {
+				id: None,
+				error: Some(Error::invalid_input(&err.to_string())),
+			}
+		}
+	};
+	let id = value.get("id").and_then(|id| id.as_str()).map(String::from);
+
+	// Every command changes something, so they all need the token
+	let result = match authorized {
+		false => Err(Error::unauthorized("Missing API token")),
+		true => serde_json::from_value::<Command>(value)
+			.map_err(|err| Error::invalid_input(&err.to_string()))
+			.and_then(|command| {
+				trace!(?id, ?command, "WebSocket command");
+				execute(datastore, command)
+			}),
+	};
+
+	Reply::Result {
+		id,
+		error: result.err(),
+	}
+}
+
+async fn send(session: &mut Session, reply: &Reply) -> Result<(), actix_ws::Closed> {
+	match serde_json::to_string(reply) {
+		Ok(text) => session.text(text).await,
+		Err(err) => {
+			warn!(%err, "Unable to serialize WebSocket reply");
+			Ok(())
+		}
+	}
+}
+
+async fn run_session(
+	mut session: Session,
+	mut messages: MessageStream,
+	datastore: Arc<Store>,
+	replay: Replay<CardEvent>,
+	mut receiver: Receiver<Published<CardEvent>>,
+	authorized: bool,
+) {
+	let replay: Vec<Reply> = match replay {
+		Replay::Events(events) => events.into_iter().map(Reply::from).collect(),
+		Replay::Reset => vec![Reply::Reset],
+	};
+	for reply in replay {
+		if send(&mut session, &reply).await.is_err() {
+			return;
+		}
+	}
+
+	loop {
+		let reply = tokio::select! {
+			event = receiver.recv() => match event {
+				Ok(event) => Reply::from(event),
+				// A session which falls behind has lost events, so it is told to reload just like after a reset
+				Err(RecvError::Lagged(_)) => Reply::Reset,
+				Err(RecvError::Closed) => break,
+			},
+			message = messages.next() => match message {
+				Some(Ok(Message::Text(text))) => handle(&datastore, &text, authorized),
+				Some(Ok(Message::Binary(_))) => Reply::Result {
+					id: None,
+					error: Some(Error::invalid_input("Commands have to be sent as text")),
+				},
+				Some(Ok(Message::Ping(bytes))) => {
+					if session.pong(&bytes).await.is_err() {
+						return;
+					}
+					continue;
+				}
+				Some(Ok(Message::Close(reason))) => {
+					debug!(?reason, "WebSocket closed by client");
+					_ = session.close(reason).await;
+					return;
+				}
+				Some(Ok(_)) => continue,
+				Some(Err(err)) => {
+					warn!(%err, "WebSocket protocol error");
+					break;
+				}
+				None => break,
+			},
+		};
+
+		if send(&mut session, &reply).await.is_err() {
+			return;
+		}
+	}
+
+	_ = session.close(None).await;
+}
+
+#[utoipa::path(
+	tag = "meta",
+	params(SessionQuery),
+	responses(
+		(status = 101, description = "WebSocket session streaming `CardEvent`s and accepting commands"),
+		Error
+	)
+)]
+#[get("/ws")]
+#[instrument(skip(req, body, query, datastore, token))]
+pub(crate) async fn websocket(
+	req: HttpRequest,
+	body: web::Payload,
+	query: web::Query<SessionQuery>,
+	datastore: web::Data<Arc<Store>>,
+	token: web::Data<Token>,
+) -> Result<HttpResponse> {
+	trace!("HTTP GET /ws");
+
+	// Sessions without a token can follow events but not send commands
+	let authorized = match bearer_token(req.headers()).or(query.token.as_deref()) {
+		None => false,
+		Some(presented) if token.matches(presented) => true,
+		Some(_) => return Err(Error::unauthorized("Invalid API token").into()),
+	};
+
+	let (response, session, messages) = actix_ws::handle(&req, body)?;
+	let (replay, receiver) = datastore.subscribe(query.last_event_id);
+
+	actix_web::rt::spawn(run_session(
+		session,
+		messages,
+		datastore.get_ref().clone(),
+		replay,
+		receiver,
+		authorized,
+	));
+
+	Ok(response)
+}
@@ -64,7 +64,7 @@ export class MicroSDeck {
 	private abortController = new AbortController();
 
 	private logger: Logger | undefined;
-	private fetchProps!: { url: string, logger?: Logger | undefined };
+	private fetchProps!: { url: string, logger?: Logger | undefined, token?: string | undefined };
 
 	public eventBus = new EventTarget() as unknown as EventBus<EventType, Event | CustomEvent<BackendEvent>>;
 
@@ -98,9 +98,12 @@ export class MicroSDeck {
 
 	private pollLock: any | undefined;
 
+	// Id of the last backend event, so a reconnect only replays what was missed
+	private lastEventId: string | undefined;
+
 	private isDestructed = false;
 
-	constructor(props: { logger?: Logger, url: string }) {
+	constructor(props: { logger?: Logger, url: string, token?: string }) {
 		this.logger = props.logger;
 
 		this.eventBus.addEventListener
@@ -113,6 +116,10 @@ export class MicroSDeck {
 		this.subscribeToUpdates();
 	}
 
+	setToken(token: string | undefined) {
+		this.fetchProps = { ...this.fetchProps, token };
+	}
+
 	destruct() {
 		this.logger?.Debug("Deconstruct Called");
 		if (this.isDestructed) return;
@@ -185,7 +192,7 @@ export class MicroSDeck {
 				await new Promise(async (res) => {
 					await sleep(sleepDelay);
 
-					fetchEventTarget({ ...this.fetchProps, callback: this.handleCallback.bind(this) }, { signal })
+					fetchEventTarget({ ...this.fetchProps, callback: this.handleCallback.bind(this), lastEventId: this.lastEventId }, { signal })
 						.catch((reason) => {
 							this.logger?.Warn(`Listen was aborted with reason "${reason}"`);
 							res(0);
@@ -199,6 +206,9 @@ export class MicroSDeck {
 	}
 
 	async handleCallback(event: EventType, data?: BackendEvent) {
+		if (data?.id) {
+			this.lastEventId = data.id;
+		}
 		await this.fetch();
 		this.eventBus.dispatchEvent(new CustomEvent(event, { detail: data }));
 	}
@@ -1,20 +1,28 @@
 import Logger from 'lipe';
-import { CardAndGames, CardsAndGames, Game, MicroSDCard } from "./types.js";
+import { CardAndGames, CardChange, CardsAndGames, Game, MicroSDCard } from "./types.js";
 
 export type FetchProps = {
 	url: string,
 	logger?: Logger | undefined;
+	// Required by the backend on every request that changes something
+	token?: string | undefined;
 }
 
+// Version of the backend API this client speaks. The unprefixed routes are deprecated
+const API_VERSION = "v2";
+
 const ApplicationJsonHeaders = {
 	headers: {
 		"Content-Type": "application/json",
 	}
 }
 
-async function wrapFetch({ url, logger }: FetchProps, init?: RequestInit): Promise<any | undefined> {
+async function wrapFetch({ url, logger, token }: FetchProps, init?: RequestInit): Promise<any | undefined> {
 	try {
-		const response = await fetch(url, init);
+		const response = await fetch(url, token ? {
+			...init,
+			headers: { ...init?.headers, "Authorization": `Bearer ${token}` },
+		} : init);
 
 		if (!response.ok) {
 			logger?.Debug("Fetching {url} returned a non 200 status code {status}. Response: {statusText}", response as any);
@@ -32,7 +40,8 @@ async function wrapFetch({ url, logger }: FetchProps, init?: RequestInit): Promi
 	return undefined;
 }
 
-export type EventType = "start" | "close" | "abort" | "message" | "insert" | "remove" | "update" | "change";
+// "reset" is sent when the events missed since the last connection are no longer available
+export type EventType = "start" | "close" | "abort" | "message" | "insert" | "remove" | "update" | "reset" | "change";
 export type Event = {
 	[key: string]: string | undefined,
 	event: EventType,
@@ -46,7 +55,9 @@ function decodeEvent(message: string, logger?: Logger): Event {
 	var result: Event = { event: "message" };
 
 	for (let line of message.split('\n')) {
-		let [key, value] = line.split(":").map(v => v.trim());
+		// Only the first colon separates the key, the data is JSON
+		const separator = line.indexOf(":");
+		const [key, value] = separator < 0 ? [line.trim(), ""] : [line.substring(0, separator).trim(), line.substring(separator + 1).trim()];
 		if (!key) {
 			throw new Error("No key was present for event " + message);
 		}
@@ -57,6 +68,14 @@ function decodeEvent(message: string, logger?: Logger): Event {
 	return result;
 }
 
+export function decodeChange(event: Event): CardChange | undefined {
+	try {
+		return event.data ? JSON.parse(event.data) : undefined;
+	} catch {
+		return undefined;
+	}
+}
+
 function decodeStreamEvents(logger?: Logger) {
 	let buffer = "";
 	let pos = 0;
@@ -103,11 +122,16 @@ function makeCallbackEventStream(callback: EventCallback) {
 	})
 }
 
-export async function fetchEventTarget({url, logger, callback}: FetchProps & { callback: EventCallback }, init?: RequestInit) {
+// Passing the id of the last event received replays whatever was missed while disconnected
+export async function fetchEventTarget({url, logger, callback, lastEventId}: FetchProps & { callback: EventCallback, lastEventId?: string }, init?: RequestInit) {
 	const eventDecoder = decodeStreamEvents(logger);
 	const outStream = makeCallbackEventStream(callback);
 
-	await fetch(`${url}/listen`, {...init, keepalive: true})
+	await fetch(`${url}/${API_VERSION}/listen`, {
+		...init,
+		keepalive: true,
+		headers: lastEventId ? { ...init?.headers, "Last-Event-ID": lastEventId } : init?.headers,
+	})
 		.then(response => {
 			response.body?.pipeThrough(new TextDecoderStream())
 				.pipeThrough(eventDecoder)
@@ -116,11 +140,11 @@ export async function fetchEventTarget({url, logger, callback}: FetchProps & { c
 }
 
 export async function fetchHealth({ url, logger }: FetchProps): Promise<boolean> {
-	return await wrapFetch({ url: `${url}/health`, logger }) !== undefined;
+	return await wrapFetch({ url: `${url}/${API_VERSION}/health`, logger }) !== undefined;
 }
 
 export async function fetchVersion({ url, logger }: FetchProps): Promise<string | undefined> {
-	return await wrapFetch({ url: `${url}/health`, logger });
+	return await wrapFetch({ url: `${url}/${API_VERSION}/health`, logger });
 }
 
 export type SettingNames = 
@@ -138,32 +162,32 @@ export type SettingNames =
 	"frontend:dismissed_docs";
 
 export async function fetchGetSetting({ url, logger, setting_name }: FetchProps & { setting_name: SettingNames }): Promise<any | undefined> {
-	const result = await wrapFetch({ url: `${url}/setting/${setting_name}`, logger });
+	const result = await wrapFetch({ url: `${url}/${API_VERSION}/setting/${setting_name}`, logger });
 	return result && JSON.parse(result) || result;
 }
 
-export async function fetchSetSetting({ url, logger, value, setting_name }: FetchProps & { setting_name: SettingNames, value: any }) {
-	await wrapFetch({ url: `${url}/setting/${setting_name}`, logger }, {
+export async function fetchSetSetting({ url, logger, token, value, setting_name }: FetchProps & { setting_name: SettingNames, value: any }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/setting/${setting_name}`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify(value),
 	});
 }
 
-export async function fetchDeleteCard({ url, logger, card }: FetchProps & { card: MicroSDCard }) {
-	await wrapFetch({ url: `${url}/card/${card.uid}`, logger }, { method: "DELETE" });
+export async function fetchDeleteCard({ url, logger, token, card }: FetchProps & { card: MicroSDCard }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/card/${card.uid}`, logger, token }, { method: "DELETE" });
 }
 
-export async function fetchUpdateCard({ url, logger, card }: FetchProps & { card: MicroSDCard }) {
-	await wrapFetch({ url: `${url}/card/${card.uid}`, logger }, {
+export async function fetchUpdateCard({ url, logger, token, card }: FetchProps & { card: MicroSDCard }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/card/${card.uid}`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify(card),
 	});
 }
 
-export async function fetchUpdateCards({ url, logger, cards }: FetchProps & { cards: MicroSDCard[] }) {
-	await wrapFetch({ url: `${url}/cards`, logger }, {
+export async function fetchUpdateCards({ url, logger, token, cards }: FetchProps & { cards: MicroSDCard[] }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/cards`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify(cards),
@@ -171,51 +195,51 @@ export async function fetchUpdateCards({ url, logger, cards }: FetchProps & { ca
 }
 
 export async function fetchCurrentCardAndGames({ url, logger }: FetchProps): Promise<CardAndGames | undefined> {
-	return await wrapFetch({ url: `${url}/current`, logger });
+	return await wrapFetch({ url: `${url}/${API_VERSION}/current`, logger });
 }
 
 export async function fetchCardsAndGames({ url, logger }: FetchProps): Promise<CardsAndGames | undefined> {
-	return await wrapFetch({ url: `${url}/list`, logger });
+	return await wrapFetch({ url: `${url}/${API_VERSION}/list`, logger });
 }
 
 export async function fetchCardsForGame({ url, logger, gameId }: FetchProps & { gameId: string }): Promise<MicroSDCard[] | undefined> {
-	return await wrapFetch({ url: `${url}/list/cards/${gameId}`, logger });
+	return await wrapFetch({ url: `${url}/${API_VERSION}/list/cards/${gameId}`, logger });
 }
 
-export async function fetchCreateGame({ url, logger, game}: FetchProps & { game: Game }) {
-	await wrapFetch({ url: `${url}/game/${game.uid}`, logger }, {
+export async function fetchCreateGame({ url, logger, token, game}: FetchProps & { game: Game }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/game/${game.uid}`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify(game),
 	});
 }
 
-export async function fetchLinkCardAndGame({ url, logger, card_id, game_id}: FetchProps & { card_id: string, game_id: string }) {
-	await wrapFetch({ url: `${url}/link`, logger }, {
+export async function fetchLinkCardAndGame({ url, logger, token, card_id, game_id}: FetchProps & { card_id: string, game_id: string }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/link`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify({card_id, game_id}),
 	});
 }
 
-export async function fetchLinkCardAndManyGames({ url, logger, card_id, game_ids}: FetchProps & { card_id: string, game_ids: string[] }) {
-	await wrapFetch({ url: `${url}/linkmany`, logger }, {
+export async function fetchLinkCardAndManyGames({ url, logger, token, card_id, game_ids}: FetchProps & { card_id: string, game_ids: string[] }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/linkmany`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify({card_id, game_ids}),
 	});
 }
 
-export async function fetchUnlinkCardAndGame({ url, logger, card_id, game_id}: FetchProps & { card_id: string, game_id: string }) {
-	await wrapFetch({ url: `${url}/unlink`, logger }, {
+export async function fetchUnlinkCardAndGame({ url, logger, token, card_id, game_id}: FetchProps & { card_id: string, game_id: string }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/unlink`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify({card_id, game_id}),
 	});
 }
 
-export async function fetchUnlinkCardAndManyGames({ url, logger, card_id, game_ids}: FetchProps & { card_id: string, game_ids: string[] }) {
-	await wrapFetch({ url: `${url}/unlinkmany`, logger }, {
+export async function fetchUnlinkCardAndManyGames({ url, logger, token, card_id, game_ids}: FetchProps & { card_id: string, game_ids: string[] }) {
+	await wrapFetch({ url: `${url}/${API_VERSION}/unlinkmany`, logger, token }, {
 		method: "POST",
 		...ApplicationJsonHeaders,
 		body: JSON.stringify({card_id, game_ids}),
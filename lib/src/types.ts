@@ -8,19 +8,52 @@ export type MicroSDCard = {
 	games: string[],
 	position: number,
 	hidden: boolean,
+	tags?: string[],
+	notes?: string | null,
+	archived?: { date: string, reason: string | null } | null,
 }
 
+// Where a game comes from. Only the watcher adds games from Steam manifests and keeps their name & size up to date
+export type GameSource = "steam_manifest" | "steam_shortcut" | "manual" | "scanned_rom" | "other_launcher";
+
 export type Game = {
 	uid: string,
 	name: string,
 	size: number,
-	is_steam: boolean,
+	source: GameSource,
+	/** @deprecated Use `source`. Still sent, true for games from Steam manifests */
+	is_steam?: boolean,
+	tags?: string[],
+	notes?: string | null,
 }
 
 export type CardAndGames = [MicroSDCard, Game[]];
 
 export type CardsAndGames = CardAndGames[];
 
+export type ChangeKind = "inserted" | "removed" | "added" | "updated" | "deleted";
+
+// Payload of the events sent by the backend. Only the id of the element that changed is present
+export type CardChange = {
+	kind: ChangeKind,
+	card_id?: string,
+	game_id?: string,
+	group_id?: string,
+	linked?: string[],
+	unlinked?: string[],
+	fields?: string[],
+}
+
+export type ApiErrorCode = "not_found" | "invalid_input" | "unauthorized" | "conflict" | "revision_mismatch" | "unavailable" | "internal";
+
+export type ApiError = {
+	code: ApiErrorCode,
+	message: string,
+	// Only present for "revision_mismatch", the element as it is now
+	revision?: number,
+	current?: unknown,
+}
+
 export type FrontendSettings = {
 	dismissed_docs: boolean	
 }
\ No newline at end of file
@@ -38,8 +38,16 @@ class Plugin:
                 case _code:
                     print("Program exited with exit code: " + _code)
                     break;
-         # Function used to clean up a plugin when it's told to unload by Decky-Loader
-    
+
+    # The backend writes the token on its first run. The frontend needs it for every request that changes something
+    async def get_token(self):
+        try:
+            with open(os.path.join(os.environ["DECKY_PLUGIN_RUNTIME_DIR"], "token")) as file:
+                return file.read().strip()
+        except (KeyError, OSError):
+            return None
+
+    # Function used to clean up a plugin when it's told to unload by Decky-Loader
     async def _unload(self):
         if self.backend_proc is not None:
             self.backend_proc.kill();
\ No newline at end of file
@@ -38,7 +38,7 @@ export function CardActionsContextMenu({ cardAndGames, currentCard, microSDeck }
 							
 							const appName = collectionStore.deckDesktopApps?.allApps.find(v => v.appid == parseInt(appId))?.display_name ?? "Unknown Game";
 
-							return microSDeck.createGame({ uid: appId, name: appName, is_steam: false, size: 0 })
+							return microSDeck.createGame({ uid: appId, name: appName, source: "steam_shortcut", size: 0 })
 								.catch(Error => Logger.Error("There was a critical error creating game: \"{Error}\"", { Error }));
 						}));
 						
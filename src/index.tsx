@@ -24,6 +24,7 @@ import { backend } from "../lib/src";
 import { version as libVersion } from "../lib/src";
 import { version } from "../package.json";
 import { fetchSetSetting } from "../lib/src/backend";
+import { LoadToken, Token } from "./lib/Token";
 
 if (!IsMatchingSemver(libVersion, version)) {
 	throw new Error("How the hell did we get here???");
@@ -98,7 +99,7 @@ function CardsList({ cardsAndGames, currentCardAndGames, microSDeck }: { cardsAn
 			interactables={CardInteractables}
 			onSave={async (entries: ReorderableEntry<MicroSDCard>[]) => {
 				await backend.fetchUpdateCards({
-					url: API_URL, logger: Logger, cards: entries.map(v => {
+					url: API_URL, logger: Logger, token: Token(), cards: entries.map(v => {
 						v.data!.position = v.position;
 						return v.data!;
 					})
@@ -144,7 +145,7 @@ function DocsCard({ frontendSettings, refresh }: { frontendSettings: FrontendSet
 					style={{ width: "100%" }}
 					onClick={() => {
 						if (set_dismiss) {
-							fetchSetSetting({ url: API_URL, logger: Logger, setting_name: "frontend:dismissed_docs", value: set_dismiss }).then(() => {
+							fetchSetSetting({ url: API_URL, logger: Logger, token: Token(), setting_name: "frontend:dismissed_docs", value: set_dismiss }).then(() => {
 								refresh();
 							});
 						}
@@ -214,6 +215,7 @@ export default definePlugin(() => {
 		window.MicroSDeck.destruct();
 	}
 	window.MicroSDeck = new MicroSDeck({ url: API_URL, logger: Logger });
+	LoadToken().then(token => window.MicroSDeck?.setToken(token));
 
 	Logger.Log("Started MicroSDeck");
 
@@ -0,0 +1,15 @@
+import { callable } from "@decky/api";
+
+// The backend generates the token on its first run, the python side of the plugin hands it over
+const getToken = callable<[], string | null>("get_token");
+
+let token: string | undefined;
+
+export async function LoadToken(): Promise<string | undefined> {
+	token = (await getToken().catch(() => null)) ?? undefined;
+	return token;
+}
+
+export function Token(): string | undefined {
+	return token;
+}
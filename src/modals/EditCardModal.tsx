@@ -37,7 +37,7 @@ export const EditCardModal: FC<EditCardProps> = ({ card, games, onConfirm, close
 	const [canSave, setCanSave] = useState<boolean>(false);
 	const [additions, setAdditions] = useState<{ [appId: string]: boolean }>({});
 	const [deletions, setDeletions] = useState<{ [appId: string]: boolean }>({});
-	const nonSteamIdsOnCard = useMemo(() => games.reduce<{ [appId: string]: boolean }>((output, game) => !game.is_steam ? Object.assign(output, { [game.uid]: true }) : output, {}), []);
+	const nonSteamIdsOnCard = useMemo(() => games.reduce<{ [appId: string]: boolean }>((output, game) => game.source !== "steam_manifest" ? Object.assign(output, { [game.uid]: true }) : output, {}), []);
 	const [checkedIds, setCheckedIds] = useState(nonSteamIdsOnCard);
 
 	const nameInputElt = <TextField value={name} onChange={onNameChange} />;
@@ -15,8 +15,8 @@ export const GamesOnCardModal: FC<GamesOnCardModalProps> = ({ card, games, addit
 	const deletionIds = deletions ?? {};
 
 	const padding = '0 8px'
-	const steamGames = games.flatMap(game => game.is_steam ? <li style={{ padding }}>{game.name}</li> : []);
-	const nonSteamGames = games.flatMap(game => !game.is_steam ? <li style={Object.assign({ padding }, deletionIds[game.uid] ? { background: '#9e1b3452' } : {})}>{game.name}</li> : []);
+	const steamGames = games.flatMap(game => game.source === "steam_manifest" ? <li style={{ padding }}>{game.name}</li> : []);
+	const nonSteamGames = games.flatMap(game => game.source !== "steam_manifest" ? <li style={Object.assign({ padding }, deletionIds[game.uid] ? { background: '#9e1b3452' } : {})}>{game.name}</li> : []);
 	const additionElements = Object.keys(additionIds).map(appId => {
 		let app;
 		return <li style={{ background: '#0c791647', padding }}>